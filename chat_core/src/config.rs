@@ -0,0 +1,471 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use tracing_subscriber::EnvFilter;
+
+// a key with this suffix is replaced by the content of the file it points to,
+// e.g. `sk_file: /run/secrets/chat_sk` sets `sk`
+const FILE_SUFFIX: &str = "_file";
+
+// where a binary finds its configuration
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigSource {
+    // name of the binary, for the usage message
+    pub name: &'static str,
+    // merged in this order when they exist, later files override earlier ones
+    pub files: &'static [&'static str],
+    // env var with the path of another file, e.g. CHAT_CONFIG
+    pub path_env: &'static str,
+    // env vars like CHAT__SERVER__PORT=7000 override single fields
+    pub env_prefix: &'static str,
+}
+
+// problems serde can't catch, as `field: what's wrong`
+pub trait Validate {
+    fn validate(&self) -> Vec<String>;
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ServerConfig {
+    pub port: u16,
+    pub db_url: String,
+    // seconds in-flight requests get to finish on SIGTERM
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    // bearer token prometheus scrapes /metrics with, it isn't served without one
+    #[serde(default)]
+    pub metrics_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TracingConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+    // RUST_LOG style directives, e.g. "info,sqlx=warn". RUST_LOG overrides it
+    #[serde(default = "default_log_filter")]
+    pub filter: String,
+    // an otlp grpc collector, e.g. http://localhost:4317. spans are only exported
+    // when it's set
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    // one line per event
+    #[default]
+    Text,
+    // multi-line, for reading logs locally
+    Pretty,
+    // one json object per line, with the fields of the request span
+    Json,
+}
+
+// configuration layers from the command line
+#[derive(Debug, Default)]
+struct ConfigArgs {
+    files: Vec<PathBuf>,
+    overrides: Vec<(String, String)>,
+}
+
+impl ConfigSource {
+    // layers from lowest to highest precedence: the defaults of the fields, the
+    // files, the file in `path_env`, files given with --config, env vars and --set
+    // flags. env and flag values are parsed as yaml scalars, so quote strings which
+    // look like numbers, e.g. CHAT__X="'1234'"
+    pub fn load<T>(&self, args: &[String]) -> Result<T>
+    where
+        T: DeserializeOwned + Validate,
+    {
+        let args = ConfigArgs::parse(args, self.name)?;
+        let mut files: Vec<PathBuf> = self
+            .files
+            .iter()
+            .map(PathBuf::from)
+            .filter(|path| path.exists())
+            .collect();
+        files.extend(env::var_os(self.path_env).map(PathBuf::from));
+        files.extend(args.files);
+        if files.is_empty() {
+            bail!("No configuration file found");
+        }
+
+        let mut layers = Vec::new();
+        for path in &files {
+            let file =
+                File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+            let layer = serde_yaml::from_reader(file)
+                .with_context(|| format!("failed to parse {}", path.display()))?;
+            layers.push(layer);
+        }
+        let overrides = env_overrides(self.env_prefix, env::vars_os())?
+            .into_iter()
+            .chain(args.overrides);
+
+        from_layers(layers, overrides)
+    }
+}
+
+// the `a.b.c=value` overrides of the env vars with the prefix. other vars aren't
+// ours, they are skipped even when they aren't valid utf-8
+fn env_overrides(
+    prefix: &str,
+    vars: impl IntoIterator<Item = (OsString, OsString)>,
+) -> Result<Vec<(String, String)>> {
+    let mut overrides = Vec::new();
+    for (key, value) in vars {
+        let Some(key) = key.to_str().and_then(|key| key.strip_prefix(prefix)) else {
+            continue;
+        };
+        let Some(value) = value.to_str() else {
+            bail!("{}{}: must be valid utf-8", prefix, key);
+        };
+        overrides.push((key.to_lowercase().replace("__", "."), value.to_string()));
+    }
+    Ok(overrides)
+}
+
+// merge the layers, apply the `a.b.c=value` overrides, then deserialize and validate
+// the result. all validation errors are reported at once
+pub fn from_layers<T>(
+    layers: Vec<Value>,
+    overrides: impl IntoIterator<Item = (String, String)>,
+) -> Result<T>
+where
+    T: DeserializeOwned + Validate,
+{
+    let mut value = Value::Mapping(Mapping::new());
+    for layer in layers {
+        merge(&mut value, layer);
+    }
+    for (key, raw) in overrides {
+        set_path(&mut value, &key, parse_scalar(&raw));
+    }
+    load_files(&mut value, "")?;
+
+    let config: T = serde_path_to_error::deserialize(value)
+        .map_err(|e| anyhow!("invalid configuration:\n  - {}: {}", e.path(), e.inner()))?;
+    let errors = config.validate();
+    if !errors.is_empty() {
+        bail!("invalid configuration:\n  - {}", errors.join("\n  - "));
+    }
+
+    Ok(config)
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+impl Validate for ServerConfig {
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.port == 0 {
+            errors.push("server.port: must not be 0".to_string());
+        }
+        if !self.db_url.starts_with("postgres://") && !self.db_url.starts_with("postgresql://") {
+            errors.push("server.db_url: must be a postgres:// url".to_string());
+        }
+        if self
+            .metrics_token
+            .as_ref()
+            .is_some_and(|token| token.trim().len() < 16)
+        {
+            errors.push("server.metrics_token: must be at least 16 characters".to_string());
+        }
+
+        errors
+    }
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            filter: default_log_filter(),
+            otlp_endpoint: None,
+        }
+    }
+}
+
+fn default_log_filter() -> String {
+    "info".to_string()
+}
+
+impl Validate for TracingConfig {
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Err(e) = EnvFilter::try_new(&self.filter) {
+            errors.push(format!("tracing.filter: {}", e));
+        }
+        if let Some(endpoint) = &self.otlp_endpoint {
+            if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                errors.push("tracing.otlp_endpoint: must be an http(s) url".to_string());
+            }
+        }
+
+        errors
+    }
+}
+
+impl ConfigArgs {
+    fn parse(args: &[String], name: &str) -> Result<Self> {
+        let usage = format!(
+            "usage: {} [--config <path>]... [--set <key>=<value>]...",
+            name
+        );
+        let mut ret = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let value = match value.or_else(|| args.next().cloned()) {
+                Some(value) if flag == "--config" || flag == "--set" => value,
+                _ => bail!("unexpected argument {}\n{}", arg, usage),
+            };
+            if flag == "--config" {
+                ret.files.push(value.into());
+            } else {
+                let Some((key, value)) = value.split_once('=') else {
+                    bail!("--set expects <key>=<value>, got {}\n{}", value, usage);
+                };
+                ret.overrides.push((key.to_string(), value.to_string()));
+            }
+        }
+
+        Ok(ret)
+    }
+}
+
+// mappings are merged key by key, anything else is replaced
+fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Mapping(base), Value::Mapping(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+// set `a.b.c`, creating the mappings on the way
+fn set_path(value: &mut Value, path: &str, new: Value) {
+    let mut current = value;
+    for key in path.split('.') {
+        if !current.is_mapping() {
+            *current = Value::Mapping(Mapping::new());
+        }
+        let Value::Mapping(map) = current else {
+            unreachable!()
+        };
+        current = map
+            .entry(Value::String(key.to_string()))
+            .or_insert(Value::Null);
+    }
+    *current = new;
+}
+
+// numbers, booleans and flow sequences like `[password, ldap]`, anything else is
+// a string. yaml would parse a PEM key as a document separator
+fn parse_scalar(raw: &str) -> Value {
+    match serde_yaml::from_str(raw) {
+        Ok(value @ (Value::Bool(_) | Value::Number(_) | Value::Sequence(_))) => value,
+        Ok(Value::String(s)) if raw.starts_with(['\'', '"']) => Value::String(s),
+        _ => Value::String(raw.to_string()),
+    }
+}
+
+// replace `x_file: path` by `x: <content of path>`
+fn load_files(value: &mut Value, prefix: &str) -> Result<()> {
+    let Value::Mapping(map) = value else {
+        return Ok(());
+    };
+
+    let keys: Vec<String> = map
+        .keys()
+        .filter_map(|key| key.as_str().map(|s| s.to_string()))
+        .collect();
+    for key in keys {
+        let path = format!("{}{}", prefix, key);
+        let Some(name) = key.strip_suffix(FILE_SUFFIX) else {
+            if let Some(value) = map.get_mut(key.as_str()) {
+                load_files(value, &format!("{}.", path))?;
+            }
+            continue;
+        };
+        let Some(Value::String(file)) = map.remove(key.as_str()) else {
+            bail!("invalid configuration:\n  - {}: must be a file path", path);
+        };
+        let content = fs::read_to_string(&file).map_err(|e| {
+            anyhow!(
+                "invalid configuration:\n  - {}: can't read {}: {}",
+                path,
+                file,
+                e
+            )
+        })?;
+        map.insert(
+            Value::String(name.to_string()),
+            Value::String(content.trim_end().to_string()),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct TestConfig {
+        server: ServerConfig,
+        #[serde(default)]
+        secret: Option<String>,
+        #[serde(default)]
+        names: Vec<String>,
+    }
+
+    impl Validate for TestConfig {
+        fn validate(&self) -> Vec<String> {
+            self.server.validate()
+        }
+    }
+
+    fn overrides(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn base_layer() -> Value {
+        serde_yaml::from_str("server:\n  port: 6688\n  db_url: postgres://localhost/chat").unwrap()
+    }
+
+    #[test]
+    fn config_layers_should_merge() -> Result<()> {
+        let layer = serde_yaml::from_str("server:\n  port: 7000")?;
+        let pairs = [("names", "[alice, bob]"), ("secret", "'1234'")];
+        let config: TestConfig = from_layers(vec![base_layer(), layer], overrides(&pairs))?;
+
+        assert_eq!(config.server.port, 7000);
+        // fields of the lower layers are kept
+        assert_eq!(config.server.db_url, "postgres://localhost/chat");
+        assert_eq!(config.names, vec!["alice", "bob"]);
+        assert_eq!(config.secret.as_deref(), Some("1234"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn config_values_should_load_from_files() -> Result<()> {
+        let path = env::temp_dir().join(format!("chat_core_secret_{}", std::process::id()));
+        writeln!(File::create(&path)?, "s3cret")?;
+
+        let pairs = [("secret_file", path.to_str().unwrap())];
+        let config: TestConfig = from_layers(vec![base_layer()], overrides(&pairs))?;
+        assert_eq!(config.secret.as_deref(), Some("s3cret"));
+        fs::remove_file(path)?;
+
+        let pairs = [("secret_file", "/nonexistent/secret")];
+        let ret = from_layers::<TestConfig>(vec![base_layer()], overrides(&pairs));
+        assert!(ret
+            .unwrap_err()
+            .to_string()
+            .contains("secret_file: can't read"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_config_should_be_reported() -> Result<()> {
+        let pairs = [("server.port", "http")];
+        let err = from_layers::<TestConfig>(vec![base_layer()], overrides(&pairs)).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("invalid configuration:\n  - server.port:"));
+
+        let pairs = [("server.port", "0"), ("server.db_url", "mysql://localhost")];
+        let err = from_layers::<TestConfig>(vec![base_layer()], overrides(&pairs)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid configuration:\n  \
+             - server.port: must not be 0\n  \
+             - server.db_url: must be a postgres:// url"
+        );
+
+        let pairs = [("server.metrics_token", "short")];
+        let err = from_layers::<TestConfig>(vec![base_layer()], overrides(&pairs)).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("server.metrics_token: must be at least 16 characters"));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn env_overrides_should_skip_other_vars() -> Result<()> {
+        use std::os::unix::ffi::OsStringExt;
+
+        let invalid = || OsString::from_vec(vec![0xff, 0xfe]);
+        let vars = vec![
+            (OsString::from("CHAT__SERVER__PORT"), OsString::from("7000")),
+            (OsString::from("OTHER"), invalid()),
+            (invalid(), OsString::from("value")),
+        ];
+        let ret = env_overrides("CHAT__", vars)?;
+        assert_eq!(ret, overrides(&[("server.port", "7000")]));
+
+        let vars = vec![(OsString::from("CHAT__AUTH__SK"), invalid())];
+        let err = env_overrides("CHAT__", vars).unwrap_err();
+        assert_eq!(err.to_string(), "CHAT__AUTH__SK: must be valid utf-8");
+
+        Ok(())
+    }
+
+    #[test]
+    fn config_args_should_parse() -> Result<()> {
+        let args: Vec<String> = [
+            "--config",
+            "a.yml",
+            "--config=b.yml",
+            "--set",
+            "server.port=7000",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let args = ConfigArgs::parse(&args, "test")?;
+        assert_eq!(
+            args.files,
+            vec![PathBuf::from("a.yml"), PathBuf::from("b.yml")]
+        );
+        assert_eq!(args.overrides, overrides(&[("server.port", "7000")]));
+
+        assert!(ConfigArgs::parse(&["--port".to_string()], "test").is_err());
+        let args = ["--set".to_string(), "port".to_string()];
+        assert!(ConfigArgs::parse(&args, "test").is_err());
+
+        Ok(())
+    }
+}
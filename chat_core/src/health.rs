@@ -0,0 +1,172 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use sqlx::{migrate::Migrator, PgPool};
+use tokio::sync::watch;
+
+// the migrations both servers expect to be applied
+static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+// shared by the server and its shutdown handling, once draining the server
+// reports not ready so the load balancer stops sending it new requests
+#[derive(Debug, Clone)]
+pub struct Readiness(Arc<watch::Sender<bool>>);
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReadyReport {
+    ready: bool,
+    // check name -> "ok" or what's wrong
+    checks: BTreeMap<&'static str, String>,
+}
+
+// the process is up and serving requests
+pub async fn healthz_handler() -> impl IntoResponse {
+    (StatusCode::OK, "ok")
+}
+
+impl Readiness {
+    pub fn drain(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    // resolves once the server starts draining, e.g. to end long lived streams
+    pub async fn draining(&self) {
+        let mut rx = self.0.subscribe();
+        // the sender lives in self, so this can't fail
+        let _ = rx.wait_for(|draining| *draining).await;
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+}
+
+impl ReadyReport {
+    // the checks every server needs, add its own with `check`
+    pub async fn new(readiness: &Readiness, pool: &PgPool) -> Self {
+        let draining = if readiness.is_draining() {
+            Err("shutting down".to_string())
+        } else {
+            Ok(())
+        };
+
+        Self {
+            ready: true,
+            checks: BTreeMap::new(),
+        }
+        .check("shutdown", draining)
+        .check("database", check_database(pool).await)
+        .check("migrations", check_migrations(pool).await)
+    }
+
+    pub fn check(mut self, name: &'static str, result: Result<(), String>) -> Self {
+        let status = match result {
+            Ok(()) => "ok".to_string(),
+            Err(e) => {
+                self.ready = false;
+                e
+            }
+        };
+        self.checks.insert(name, status);
+        self
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+}
+
+impl IntoResponse for ReadyReport {
+    fn into_response(self) -> Response {
+        let status = if self.ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+async fn check_database(pool: &PgPool) -> Result<(), String> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+async fn check_migrations(pool: &PgPool) -> Result<(), String> {
+    let applied: Vec<(i64,)> = sqlx::query_as("SELECT version FROM _sqlx_migrations WHERE success")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let applied: HashSet<i64> = applied.into_iter().map(|(v,)| v).collect();
+
+    let pending: Vec<String> = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| m.version.to_string())
+        .collect();
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("pending: {}", pending.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_check_should_make_report_not_ready() {
+        let report = ReadyReport {
+            ready: true,
+            checks: BTreeMap::new(),
+        }
+        .check("database", Ok(()));
+        assert!(report.is_ready());
+
+        let report = report.check("listener", Err("disconnected".to_string()));
+        assert!(!report.is_ready());
+        assert_eq!(report.checks["database"], "ok");
+        assert_eq!(report.checks["listener"], "disconnected");
+    }
+
+    #[test]
+    fn readiness_should_turn_false_when_draining() {
+        let readiness = Readiness::default();
+        let shared = readiness.clone();
+        assert!(!readiness.is_draining());
+        shared.drain();
+        assert!(readiness.is_draining());
+    }
+
+    #[tokio::test]
+    async fn draining_should_resolve_after_drain() {
+        let readiness = Readiness::default();
+        let waiter = tokio::spawn({
+            let readiness = readiness.clone();
+            async move { readiness.draining().await }
+        });
+        readiness.drain();
+        waiter.await.unwrap();
+
+        // and right away once drained
+        readiness.draining().await;
+    }
+}
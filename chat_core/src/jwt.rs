@@ -0,0 +1,80 @@
+use jwt_simple::prelude::*;
+use serde::de::DeserializeOwned;
+use sqlx::PgPool;
+
+// access tokens are issued by chat_server for the web app
+pub const JWT_ISSUER: &str = "chat_server";
+pub const JWT_AUDIENCE: &str = "chat_web";
+
+pub struct DecodingKey(Ed25519PublicKey);
+
+impl DecodingKey {
+    pub fn load(pem: &str) -> Result<Self, jwt_simple::Error> {
+        Ok(Self(Ed25519PublicKey::from_pem(pem)?))
+    }
+
+    // the claims of an access token
+    pub fn verify<T>(&self, token: &str) -> Result<T, jwt_simple::Error>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        self.verify_for(token, JWT_AUDIENCE)
+    }
+
+    // the claims of an access token and when it was issued, in seconds since the
+    // epoch, to check it with `token_revoked`
+    pub fn verify_issued<T>(&self, token: &str) -> Result<(T, u64), jwt_simple::Error>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let claims = self.verify_claims::<T>(token, JWT_AUDIENCE)?;
+        let issued_at = claims.issued_at.map(|v| v.as_secs()).unwrap_or_default();
+        Ok((claims.custom, issued_at))
+    }
+
+    // the claims of another kind of token chat_server issues
+    pub fn verify_for<T>(&self, token: &str, audience: &str) -> Result<T, jwt_simple::Error>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        Ok(self.verify_claims(token, audience)?.custom)
+    }
+
+    fn verify_claims<T>(
+        &self,
+        token: &str,
+        audience: &str,
+    ) -> Result<JWTClaims<T>, jwt_simple::Error>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let options = VerificationOptions {
+            allowed_issuers: Some(HashSet::from_strings(&[JWT_ISSUER])),
+            allowed_audiences: Some(HashSet::from_strings(&[audience])),
+            ..Default::default()
+        };
+
+        self.0.verify_token::<T>(token, Some(options))
+    }
+}
+
+// access tokens issued before the last password change of the user are revoked.
+// tokens only have the second they were issued, one of the same second still works
+pub async fn token_revoked(
+    user_id: i64,
+    issued_at: u64,
+    pool: &PgPool,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM users
+            WHERE id = $1 AND password_changed_at >= to_timestamp($2) + INTERVAL '1 second'
+        )
+        "#,
+    )
+    .bind(user_id)
+    .bind(issued_at as f64)
+    .fetch_one(pool)
+    .await
+}
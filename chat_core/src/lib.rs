@@ -0,0 +1,18 @@
+// shared by chat_server and notify_server
+pub mod config;
+mod health;
+mod jwt;
+mod metrics;
+mod problem;
+mod shutdown;
+mod trace;
+
+pub use config::{ConfigSource, LogFormat, ServerConfig, TracingConfig, Validate};
+pub use health::{healthz_handler, Readiness, ReadyReport};
+pub use jwt::{token_revoked, DecodingKey, JWT_AUDIENCE, JWT_ISSUER};
+pub use metrics::{init_metrics, record_request, render_metrics, track_metrics};
+pub use problem::{request_id, scope_request_id, FieldError, Problem};
+pub use shutdown::{serve, Shutdown};
+pub use trace::{
+    init_tracing, link_parent, record_user, trace_headers, traceparent, MakeRequestSpan,
+};
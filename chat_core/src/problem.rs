@@ -0,0 +1,130 @@
+use std::{fmt, future::Future};
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// an RFC 7807 error response. `code` is stable, clients switch on it rather
+// than on `detail`, which is meant for humans
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: String,
+    // what's wrong with which input field, for validation errors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, code: &str, detail: impl Into<String>) -> Self {
+        Self {
+            kind: format!("urn:chat:error:{}", code),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            code: code.to_string(),
+            errors: Vec::new(),
+            request_id: request_id(),
+        }
+    }
+
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (
+            status,
+            [(header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE)],
+            Json(self),
+        )
+            .into_response()
+    }
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+// the id of the request being handled, see `scope_request_id`
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+// handles the request with its id, so the problems it ends with carry it
+pub async fn scope_request_id<F: Future>(id: String, f: F) -> F::Output {
+    REQUEST_ID.scope(id, f).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn problem_should_carry_request_id() -> anyhow::Result<()> {
+        let ret = scope_request_id("req-1".to_string(), async {
+            Problem::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "validation_failed",
+                "bad name",
+            )
+            .with_errors(vec![FieldError::new("name", "can't be empty")])
+            .into_response()
+        })
+        .await;
+        assert_eq!(ret.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(ret.headers()[header::CONTENT_TYPE], PROBLEM_CONTENT_TYPE);
+
+        let body = to_bytes(ret.into_body(), usize::MAX).await?;
+        let body = String::from_utf8(body.to_vec())?;
+        assert!(body.contains(r#""type":"urn:chat:error:validation_failed""#));
+        assert!(body.contains(r#""title":"Unprocessable Entity""#));
+        assert!(body.contains(r#""errors":[{"field":"name","message":"can't be empty"}]"#));
+        assert!(body.contains(r#""request_id":"req-1""#));
+
+        // outside of a request
+        assert_eq!(
+            Problem::new(StatusCode::NOT_FOUND, "not_found", "").request_id,
+            None
+        );
+        Ok(())
+    }
+}
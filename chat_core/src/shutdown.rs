@@ -0,0 +1,79 @@
+use std::{future::IntoFuture, net::SocketAddr, time::Duration};
+
+use anyhow::Result;
+use axum::Router;
+use sqlx::PgPool;
+use tokio::{net::TcpListener, signal};
+use tracing::{info, warn};
+
+use crate::{trace, Readiness};
+
+// what a server cleans up when it stops
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    pub readiness: Readiness,
+    pub pool: PgPool,
+    // how long in-flight requests may take to finish
+    pub timeout: Duration,
+}
+
+// serves until SIGTERM or SIGINT, then stops accepting connections, waits for
+// the in-flight requests up to the timeout, closes the pool and flushes spans
+pub async fn serve(listener: TcpListener, app: Router, shutdown: Shutdown) -> Result<()> {
+    let readiness = shutdown.readiness.clone();
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        wait_for_signal().await;
+        info!("Shutting down, draining connections");
+        readiness.drain();
+    });
+
+    let deadline = async {
+        shutdown.readiness.draining().await;
+        tokio::time::sleep(shutdown.timeout).await;
+    };
+    tokio::select! {
+        ret = server.into_future() => ret?,
+        _ = deadline => warn!(
+            "Connections not drained in {:?}, closing them",
+            shutdown.timeout
+        ),
+    }
+
+    shutdown.pool.close().await;
+    info!("Shutdown complete");
+    trace::shutdown_tracing().await;
+    Ok(())
+}
+
+async fn wait_for_signal() {
+    let ctrl_c = async {
+        if let Err(e) = signal::ctrl_c().await {
+            warn!("Failed to listen for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,212 @@
+use std::{collections::HashMap, env};
+
+use anyhow::Result;
+use axum::http::{
+    header::{self, Entry},
+    HeaderMap, HeaderName, HeaderValue, Request, Uri,
+};
+use opentelemetry::{global, trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace::Config, Resource};
+use tower_http::trace::MakeSpan;
+use tracing::{field, info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer as _,
+};
+
+use crate::config::{LogFormat, TracingConfig};
+
+// the W3C trace context header
+const TRACEPARENT: &str = "traceparent";
+const REQUEST_ID: &str = "x-request-id";
+const SENSITIVE_HEADERS: &[HeaderName] = &[
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::COOKIE,
+    header::SET_COOKIE,
+];
+const SENSITIVE_PARAM: &str = "access_token";
+
+// logs to stdout, and exports spans when an otlp endpoint is configured
+pub fn init_tracing(service: &'static str, config: &TracingConfig) -> Result<()> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let filter = match env::var("RUST_LOG") {
+        Ok(v) => EnvFilter::try_new(v)?,
+        Err(_) => EnvFilter::try_new(&config.filter)?,
+    };
+    let layer = match config.format {
+        LogFormat::Text => Layer::new().boxed(),
+        LogFormat::Pretty => Layer::new().pretty().boxed(),
+        LogFormat::Json => Layer::new()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    };
+    let otel = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let provider = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(
+                    Config::default()
+                        .with_resource(Resource::new([KeyValue::new("service.name", service)])),
+                )
+                .install_batch(runtime::Tokio)?;
+            let tracer = provider.tracer(service);
+            global::set_tracer_provider(provider);
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(layer)
+        .with(otel)
+        .init();
+    Ok(())
+}
+
+// exports the spans which are still buffered
+pub(crate) async fn shutdown_tracing() {
+    // it blocks until the exporter is done
+    let _ = tokio::task::spawn_blocking(global::shutdown_tracer_provider).await;
+}
+
+// continues the trace of the caller, if it sent one
+pub fn link_parent(span: &Span, traceparent: Option<&str>) {
+    let Some(traceparent) = traceparent else {
+        return;
+    };
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    let cx = global::get_text_map_propagator(|propagator| propagator.extract(&carrier));
+    span.set_parent(cx);
+}
+
+// the trace context of the current span, to pass along where headers can't go,
+// e.g. in NOTIFY payloads
+pub fn traceparent() -> Option<String> {
+    let mut carrier = HashMap::new();
+    let cx = Span::current().context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&cx, &mut carrier));
+    carrier.remove(TRACEPARENT)
+}
+
+// headers for outgoing requests, so the receiver can continue the trace
+pub fn trace_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(v) = traceparent().and_then(|v| HeaderValue::from_str(&v).ok()) {
+        headers.insert(HeaderName::from_static(TRACEPARENT), v);
+    }
+    headers
+}
+
+// the authenticated user of the request, for the log lines which follow
+pub fn record_user(user_id: i64, ws_id: i64) {
+    let span = Span::current();
+    span.record("user_id", user_id);
+    span.record("ws_id", ws_id);
+}
+
+// the request span of `TraceLayer`, as a child of the caller's trace. the user
+// fields are filled in by `record_user` once the request is authenticated
+#[derive(Debug, Clone, Default)]
+pub struct MakeRequestSpan;
+
+impl<B> MakeSpan<B> for MakeRequestSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let headers = request.headers();
+        let span = info_span!(
+            "request",
+            method = %request.method(),
+            uri = %redact_uri(request.uri()),
+            version = ?request.version(),
+            headers = ?redact_headers(headers),
+            request_id = headers.get(REQUEST_ID).and_then(|v| v.to_str().ok()),
+            user_id = field::Empty,
+            ws_id = field::Empty,
+        );
+        let traceparent = headers.get(TRACEPARENT).and_then(|v| v.to_str().ok());
+        link_parent(&span, traceparent);
+        span
+    }
+}
+
+// credentials are logged as `Sensitive`
+fn redact_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut headers = headers.clone();
+    for name in SENSITIVE_HEADERS {
+        if let Entry::Occupied(mut entry) = headers.entry(name) {
+            for v in entry.iter_mut() {
+                v.set_sensitive(true);
+            }
+        }
+    }
+    headers
+}
+
+// EventSource sends the token in the query
+fn redact_uri(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+    let query: Vec<&str> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((SENSITIVE_PARAM, _)) => "access_token=[redacted]",
+            _ => pair,
+        })
+        .collect();
+    format!("{}?{}", uri.path(), query.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry_sdk::trace::TracerProvider;
+
+    use super::*;
+
+    #[test]
+    fn trace_context_should_pass_through_spans() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let tracer = TracerProvider::builder().build().tracer("test");
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let parent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+            let span = info_span!("request");
+            link_parent(&span, Some(parent));
+
+            let _guard = span.enter();
+            let ret = traceparent().unwrap();
+            // same trace, with the span of the request as the parent
+            assert!(ret.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+            assert_ne!(ret, parent);
+            assert_eq!(trace_headers()[TRACEPARENT], ret.as_str());
+        });
+    }
+
+    #[test]
+    fn credentials_should_be_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        headers.insert(header::ACCEPT, "text/event-stream".parse().unwrap());
+        let ret = format!("{:?}", redact_headers(&headers));
+        assert!(!ret.contains("secret"));
+        assert!(ret.contains("Sensitive"));
+        assert!(ret.contains("text/event-stream"));
+
+        let uri: Uri = "/events?access_token=secret&x=1".parse().unwrap();
+        assert_eq!(redact_uri(&uri), "/events?access_token=[redacted]&x=1");
+        let uri: Uri = "/api/chat".parse().unwrap();
+        assert_eq!(redact_uri(&uri), "/api/chat");
+    }
+}
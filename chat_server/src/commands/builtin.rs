@@ -0,0 +1,174 @@
+use chrono::Utc;
+
+use crate::{
+    models::{
+        Chat, ChatNotifySetting, ChatType, CreateReminder, NotifyLevel, Reminder,
+        UpdateChatNotifySetting, MAX_TOPIC_LEN,
+    },
+    AppError, AppState, User,
+};
+
+use super::{parse_duration, CommandResponse};
+
+fn usage(text: &str) -> Result<CommandResponse, AppError> {
+    Ok(CommandResponse::Ephemeral(format!("Usage: {}", text)))
+}
+
+// `/topic <text>`
+pub(super) async fn topic(
+    state: &AppState,
+    chat: &Chat,
+    args: &str,
+) -> Result<CommandResponse, AppError> {
+    if args.is_empty() || args.chars().count() > MAX_TOPIC_LEN {
+        return usage("/topic <text of at most 256 characters>");
+    }
+    if chat.r#type == ChatType::Single {
+        return Ok(CommandResponse::Ephemeral(
+            "Direct messages have no topic".to_string(),
+        ));
+    }
+
+    chat.set_topic(args, &state.pool).await?;
+    Ok(CommandResponse::System(format!("set the topic: {}", args)))
+}
+
+// `/invite <email> [<email> ...]`, emails may start with @
+pub(super) async fn invite(
+    state: &AppState,
+    user: &User,
+    chat: &Chat,
+    args: &str,
+) -> Result<CommandResponse, AppError> {
+    if args.is_empty() {
+        return usage("/invite <email> [<email> ...]");
+    }
+    if chat.r#type == ChatType::Single {
+        return Ok(CommandResponse::Ephemeral(
+            "Nobody can be invited to a direct message".to_string(),
+        ));
+    }
+
+    let mut users = vec![];
+    for email in args.split_whitespace() {
+        let email = email.trim_start_matches('@');
+        match User::find_by_email(email, &state.pool).await? {
+            Some(u) if u.ws_id == user.ws_id => users.push(u),
+            _ => {
+                return Ok(CommandResponse::Ephemeral(format!(
+                    "There is no user {} in this workspace",
+                    email
+                )))
+            }
+        }
+    }
+    users.retain(|u| !chat.members.contains(&u.id));
+    if users.is_empty() {
+        return Ok(CommandResponse::Ephemeral(
+            "Everyone is already a member".to_string(),
+        ));
+    }
+
+    let ids: Vec<i64> = users.iter().map(|u| u.id).collect();
+    chat.add_members(&ids, &state.pool).await?;
+    let names: Vec<&str> = users.iter().map(|u| u.fullname.as_str()).collect();
+    Ok(CommandResponse::System(format!(
+        "invited {}",
+        names.join(", ")
+    )))
+}
+
+// `/leave`
+pub(super) async fn leave(
+    state: &AppState,
+    user: &User,
+    chat: &Chat,
+) -> Result<CommandResponse, AppError> {
+    if chat.r#type == ChatType::Single {
+        return Ok(CommandResponse::Ephemeral(
+            "Direct messages can't be left".to_string(),
+        ));
+    }
+
+    chat.remove_member(user.id as _, &state.pool).await?;
+    Ok(CommandResponse::Ephemeral(format!(
+        "You left {}",
+        chat.name
+    )))
+}
+
+// `/mute` mutes the chat, `/mute <duration>` for a while, `/mute mentions` except
+// for mentions and `/mute off` unmutes it
+pub(super) async fn mute(
+    state: &AppState,
+    user: &User,
+    chat: &Chat,
+    args: &str,
+) -> Result<CommandResponse, AppError> {
+    let current = ChatNotifySetting::get(user.id as _, chat.id as _, &state.pool).await?;
+    let (input, text) = match args {
+        "" => (
+            UpdateChatNotifySetting {
+                level: NotifyLevel::None,
+                muted_until: None,
+            },
+            format!("Muted {}", chat.name),
+        ),
+        "mentions" => (
+            UpdateChatNotifySetting {
+                level: NotifyLevel::Mentions,
+                muted_until: None,
+            },
+            format!("Muted {} except for mentions", chat.name),
+        ),
+        "off" => (
+            UpdateChatNotifySetting {
+                level: NotifyLevel::All,
+                muted_until: None,
+            },
+            format!("Unmuted {}", chat.name),
+        ),
+        args => match parse_duration(args) {
+            Some(duration) => (
+                UpdateChatNotifySetting {
+                    level: current.level,
+                    muted_until: Some(Utc::now() + duration),
+                },
+                format!("Muted {} for {}", chat.name, args),
+            ),
+            None => return usage("/mute [<duration> | mentions | off], e.g. /mute 2h"),
+        },
+    };
+
+    ChatNotifySetting::update(user.id as _, chat.id as _, &input, &state.pool).await?;
+    Ok(CommandResponse::Ephemeral(text))
+}
+
+// `/remind [me] [in] <duration> <text>`
+pub(super) async fn remind(
+    state: &AppState,
+    user: &User,
+    chat: &Chat,
+    args: &str,
+) -> Result<CommandResponse, AppError> {
+    let args = args.strip_prefix("me ").unwrap_or(args).trim_start();
+    let args = args.strip_prefix("in ").unwrap_or(args).trim_start();
+    let (duration, text) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let (Some(duration), false) = (parse_duration(duration), text.trim().is_empty()) else {
+        return usage("/remind [me] [in] <duration> <text>, e.g. /remind me in 2h to deploy");
+    };
+
+    let remind_at = Utc::now() + duration;
+    let input = CreateReminder {
+        chat_id: chat.id as _,
+        message_id: None,
+        text: text.to_string(),
+        remind_at,
+    };
+    Reminder::create(&input, user.id as _, &state.pool).await?;
+    Ok(CommandResponse::Ephemeral(format!(
+        "I will remind you at {}: {}",
+        remind_at.format("%Y-%m-%d %H:%M UTC"),
+        text.trim()
+    )))
+}
@@ -0,0 +1,244 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    models::{Chat, SlashCommand},
+    utils::{describe_error, sign_payload, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    AppState, User,
+};
+
+use super::CommandResponse;
+
+// slack gives slash commands 3 seconds, be a bit more lenient
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+// a reply only carries a message, anything bigger is not read
+const MAX_REPLY_BYTES: usize = 64 * 1024;
+
+// what the callback url of a custom command receives
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CommandRequest {
+    pub command: String,
+    pub text: String,
+    pub user_id: i64,
+    pub user_name: String,
+    pub chat_id: i64,
+    pub workspace_id: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ResponseType {
+    #[default]
+    Ephemeral,
+    InChannel,
+}
+
+// what the callback url answers with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CommandReply {
+    pub text: String,
+    #[serde(default)]
+    pub response_type: ResponseType,
+}
+
+// failures are reported to the user who ran the command, not as an error response
+pub(super) async fn call(
+    state: &AppState,
+    user: &User,
+    chat: &Chat,
+    command: &SlashCommand,
+    args: &str,
+) -> CommandResponse {
+    match request(state, user, chat, command, args).await {
+        Ok(reply) if reply.text.trim().is_empty() => {
+            CommandResponse::Ephemeral(format!("/{} returned an empty response", command.name))
+        }
+        Ok(CommandReply {
+            text,
+            response_type: ResponseType::Ephemeral,
+        }) => CommandResponse::Ephemeral(text),
+        Ok(CommandReply {
+            text,
+            response_type: ResponseType::InChannel,
+        }) => CommandResponse::InChannel(text),
+        Err(e) => {
+            warn!("Failed to call /{} at {}: {}", command.name, command.url, e);
+            // the details could tell the user about the network behind the url
+            let reason = match e.downcast_ref::<reqwest::Error>() {
+                Some(e) => describe_error(e),
+                None => "request failed",
+            };
+            CommandResponse::Ephemeral(format!("/{} failed: {}", command.name, reason))
+        }
+    }
+}
+
+async fn request(
+    state: &AppState,
+    user: &User,
+    chat: &Chat,
+    command: &SlashCommand,
+    args: &str,
+) -> anyhow::Result<CommandReply> {
+    let body = CommandRequest {
+        command: format!("/{}", command.name),
+        text: args.to_string(),
+        user_id: user.id,
+        user_name: user.fullname.clone(),
+        chat_id: chat.id,
+        workspace_id: chat.ws_id,
+    };
+    let body = serde_json::to_vec(&body)?;
+    let timestamp = Utc::now().timestamp();
+    let signature = sign_payload(&command.signing_secret(&state.ck)?, timestamp, &body);
+
+    let mut res = state
+        .http
+        .post(&command.url)
+        .timeout(COMMAND_TIMEOUT)
+        .headers(chat_core::trace_headers())
+        .header(CONTENT_TYPE, "application/json")
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(SIGNATURE_HEADER, format!("sha256={}", signature))
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    if res
+        .content_length()
+        .is_some_and(|len| len > MAX_REPLY_BYTES as u64)
+    {
+        anyhow::bail!("reply is larger than {} bytes", MAX_REPLY_BYTES);
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        if body.len() + chunk.len() > MAX_REPLY_BYTES {
+            anyhow::bail!("reply is larger than {} bytes", MAX_REPLY_BYTES);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(serde_json::from_slice(&body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        extract::State as AxumState,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+        Json, Router,
+    };
+    use tokio::net::TcpListener;
+
+    use crate::{
+        models::{ChatType, CreateChat, CreateSlashCommand, CreateUser},
+        AppConfig,
+    };
+
+    use super::*;
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+    async fn deploy(
+        AxumState(received): AxumState<Received>,
+        headers: HeaderMap,
+        body: String,
+    ) -> Json<CommandReply> {
+        received.lock().unwrap().push((headers, body));
+        Json(CommandReply {
+            text: "deploying main".to_string(),
+            response_type: ResponseType::InChannel,
+        })
+    }
+
+    #[tokio::test]
+    async fn call_should_sign_the_request() -> anyhow::Result<()> {
+        let mut config = AppConfig::load()?;
+        // the command is served on localhost
+        config.outbound.allow_insecure = true;
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+        let received = Received::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let app = Router::new()
+            .route("/deploy", post(deploy))
+            .route(
+                "/broken",
+                post(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+            )
+            .route(
+                "/huge",
+                post(|| async {
+                    Json(CommandReply {
+                        text: "a".repeat(MAX_REPLY_BYTES),
+                        response_type: ResponseType::InChannel,
+                    })
+                }),
+            )
+            .with_state(received.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let input = CreateUser::new("acme", "Alice", "alice@acme.org", "hunter42");
+        let user = User::create(&input, &state.pool).await?;
+        let input = CreateChat::new("general", ChatType::PublicChannel, &[]);
+        let chat = Chat::create(&input, user.ws_id as _, user.id as _, &state.pool).await?;
+        let input = CreateSlashCommand {
+            name: "/Deploy".to_string(),
+            url: format!("http://{}/deploy", addr),
+            description: "deploy a branch".to_string(),
+        };
+        let (command, secret) = SlashCommand::create(
+            &input,
+            &user,
+            &state.config.outbound,
+            &state.ck,
+            &state.pool,
+        )
+        .await?;
+        assert_eq!(command.name, "deploy");
+
+        let ret = call(&state, &user, &chat, &command, "main").await;
+        assert_eq!(
+            ret,
+            CommandResponse::InChannel("deploying main".to_string())
+        );
+        {
+            let received = received.lock().unwrap();
+            let (headers, body) = &received[0];
+            let timestamp: i64 = headers[TIMESTAMP_HEADER].to_str()?.parse()?;
+            let expected = sign_payload(secret.as_bytes(), timestamp, body.as_bytes());
+            assert_eq!(headers[SIGNATURE_HEADER], format!("sha256={}", expected));
+            let request: CommandRequest = serde_json::from_str(body)?;
+            assert_eq!(request.command, "/deploy");
+            assert_eq!(request.text, "main");
+            assert_eq!(request.user_name, "Alice");
+        }
+
+        let command = SlashCommand {
+            url: format!("http://{}/broken", addr),
+            ..command
+        };
+        let ret = call(&state, &user, &chat, &command, "main").await;
+        assert!(
+            matches!(ret, CommandResponse::Ephemeral(text) if text.starts_with("/deploy failed"))
+        );
+
+        let command = SlashCommand {
+            url: format!("http://{}/huge", addr),
+            ..command
+        };
+        let ret = call(&state, &user, &chat, &command, "main").await;
+        assert!(
+            matches!(ret, CommandResponse::Ephemeral(text) if text.starts_with("/deploy failed"))
+        );
+
+        Ok(())
+    }
+}
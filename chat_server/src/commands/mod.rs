@@ -0,0 +1,330 @@
+mod builtin;
+mod custom;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    middlewares::TokenScopes,
+    models::{ApiScope, Chat, CreateMessage, Message, MessageKind, SlashCommand},
+    AppError, AppState, User,
+};
+
+// pg_notify payloads are limited to 8000 bytes, the text of an ephemeral message
+// stays well within it even with escaped characters
+const MAX_EPHEMERAL_LEN: usize = 1000;
+
+// a message starting with a slash, e.g. `/topic release planning`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CommandInput {
+    pub name: String,
+    pub args: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CommandResponse {
+    // only the user who ran the command sees it
+    Ephemeral(String),
+    // posted to the chat as a message of the user
+    InChannel(String),
+    // posted to the chat as a system message about something the user did
+    System(String),
+}
+
+// sent to the sessions of the user through notify_server
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub(crate) struct EphemeralMessage {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CommandInput {
+    // a message starting with `//` is not a command, but a message starting with `/`
+    pub fn parse(content: &str) -> Option<Self> {
+        let rest = content.trim_start().strip_prefix('/')?;
+        if rest.starts_with('/') {
+            return None;
+        }
+        let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        if name.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            name: name.to_lowercase(),
+            args: args.trim().to_string(),
+        })
+    }
+}
+
+impl EphemeralMessage {
+    // a long response of a custom command is cut
+    pub fn new(chat_id: u64, user_id: i64, text: String) -> Self {
+        let text = match text.char_indices().nth(MAX_EPHEMERAL_LEN) {
+            Some((idx, _)) => format!("{}…", &text[..idx]),
+            None => text,
+        };
+        Self {
+            chat_id: chat_id as _,
+            user_id,
+            text,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub async fn publish(&self, pool: &PgPool) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            SELECT pg_notify('ephemeral_message', json_build_object(
+                'chat_id', $1::BIGINT, 'user_id', $2::BIGINT, 'text', $3::TEXT,
+                'created_at', $4::TIMESTAMPTZ)::text)
+            "#,
+        )
+        .bind(self.chat_id)
+        .bind(self.user_id)
+        .bind(&self.text)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// run the command, built-in commands take precedence over the custom ones of the workspace.
+// an api token needs the scope of what the command changes, on top of message:write
+pub(crate) async fn execute(
+    state: &AppState,
+    user: &User,
+    scopes: Option<&TokenScopes>,
+    chat_id: u64,
+    input: &CommandInput,
+) -> Result<CommandResponse, AppError> {
+    let chat = Chat::get_member_chat(chat_id, user.id as _, &state.pool).await?;
+    if chat.archived {
+        return Err(AppError::Forbidden(format!("chat {} is archived", chat_id)));
+    }
+    match input.name.as_str() {
+        "topic" | "invite" | "leave" => TokenScopes::check(scopes, ApiScope::ChatWrite)?,
+        "mute" => TokenScopes::check(scopes, ApiScope::UserWrite)?,
+        _ => {}
+    }
+
+    match input.name.as_str() {
+        "topic" => builtin::topic(state, &chat, &input.args).await,
+        "invite" => builtin::invite(state, user, &chat, &input.args).await,
+        "leave" => builtin::leave(state, user, &chat).await,
+        "mute" => builtin::mute(state, user, &chat, &input.args).await,
+        "remind" => builtin::remind(state, user, &chat, &input.args).await,
+        name => match SlashCommand::find_by_name(user.ws_id as _, name, &state.pool).await? {
+            Some(command) => Ok(custom::call(state, user, &chat, &command, &input.args).await),
+            None => Ok(CommandResponse::Ephemeral(format!(
+                "/{} is not a command",
+                name
+            ))),
+        },
+    }
+}
+
+// run the command and deliver its response, instead of creating the message
+pub(crate) async fn run_command(
+    state: &AppState,
+    user: &User,
+    scopes: Option<&TokenScopes>,
+    chat_id: u64,
+    input: &CommandInput,
+) -> Result<Response, AppError> {
+    let (content, kind) = match execute(state, user, scopes, chat_id, input).await? {
+        CommandResponse::Ephemeral(text) => {
+            let message = EphemeralMessage::new(chat_id, user.id, text);
+            message.publish(&state.pool).await?;
+            return Ok((StatusCode::OK, Json(message)).into_response());
+        }
+        CommandResponse::InChannel(text) => (text, MessageKind::Text),
+        CommandResponse::System(text) => (text, MessageKind::System),
+    };
+
+    let input = CreateMessage {
+        content,
+        kind,
+        ..Default::default()
+    };
+    let message = Message::create(&input, chat_id, user.id as _, &state.pool).await?;
+    Ok((StatusCode::CREATED, Json(message)).into_response())
+}
+
+// e.g. 30m, 2h, 1d or 1w
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    let unit_at = s.find(|c: char| !c.is_ascii_digit())?;
+    let (n, unit) = s.split_at(unit_at);
+    let n: i64 = n.parse().ok()?;
+    let duration = match unit {
+        "m" | "min" | "mins" => Duration::minutes(n),
+        "h" | "hr" | "hrs" => Duration::hours(n),
+        "d" | "day" | "days" => Duration::days(n),
+        "w" | "week" | "weeks" => Duration::weeks(n),
+        _ => return None,
+    };
+
+    (n > 0 && duration <= Duration::days(365)).then_some(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        models::{ChatNotifySetting, ChatType, CreateChat, CreateUser, ListMessages, NotifyLevel},
+        AppConfig,
+    };
+
+    use super::*;
+
+    #[test]
+    fn command_input_parse_should_work() {
+        let input = CommandInput::parse("/Topic  release planning ").unwrap();
+        assert_eq!(input.name, "topic");
+        assert_eq!(input.args, "release planning");
+        assert_eq!(CommandInput::parse("/leave").unwrap().args, "");
+        assert_eq!(CommandInput::parse("//etc/hosts"), None);
+        assert_eq!(CommandInput::parse("/ hi"), None);
+        assert_eq!(CommandInput::parse("hello /topic"), None);
+    }
+
+    #[test]
+    fn parse_duration_should_work() {
+        assert_eq!(parse_duration("30m"), Some(Duration::minutes(30)));
+        assert_eq!(parse_duration("2h"), Some(Duration::hours(2)));
+        assert_eq!(parse_duration("1w"), Some(Duration::weeks(1)));
+        assert_eq!(parse_duration("0m"), None);
+        assert_eq!(parse_duration("2y"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("100w"), None);
+    }
+
+    #[tokio::test]
+    async fn builtin_commands_should_work() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let input = CreateUser::new("acme", "Alice", "alice@acme.org", "hunter42");
+        let alice = User::create(&input, &state.pool).await?;
+        let input = CreateUser::new("acme", "Bob", "bob@acme.org", "hunter42");
+        let bob = User::create(&input, &state.pool).await?;
+        let input = CreateChat::new("general", ChatType::PublicChannel, &[]);
+        let chat = Chat::create(&input, alice.ws_id as _, alice.id as _, &state.pool).await?;
+        let run = |user: &User, content: &str| {
+            let (state, user) = (state.clone(), user.clone());
+            let input = CommandInput::parse(content).unwrap();
+            async move { execute(&state, &user, None, chat.id as _, &input).await }
+        };
+
+        let ret = run(&alice, "/topic release planning").await?;
+        assert_eq!(
+            ret,
+            CommandResponse::System("set the topic: release planning".to_string())
+        );
+        let ret = run(&alice, "/invite @bob@acme.org").await?;
+        assert_eq!(ret, CommandResponse::System("invited Bob".to_string()));
+        let chat = Chat::get_member_chat(chat.id as _, bob.id as _, &state.pool).await?;
+        assert_eq!(chat.topic.as_deref(), Some("release planning"));
+
+        run(&bob, "/mute 2h").await?;
+        let setting = ChatNotifySetting::get(bob.id as _, chat.id as _, &state.pool).await?;
+        assert_eq!(setting.level, NotifyLevel::All);
+        assert!(setting.muted_until.is_some());
+        run(&bob, "/mute mentions").await?;
+        let setting = ChatNotifySetting::get(bob.id as _, chat.id as _, &state.pool).await?;
+        assert_eq!(setting.level, NotifyLevel::Mentions);
+        assert_eq!(setting.muted_until, None);
+
+        let ret = run(&bob, "/remind me in 30m to review the release notes").await?;
+        assert!(
+            matches!(ret, CommandResponse::Ephemeral(text) if text.ends_with("to review the release notes"))
+        );
+        let ret = run(&bob, "/remind tomorrow").await?;
+        assert!(matches!(ret, CommandResponse::Ephemeral(text) if text.starts_with("Usage:")));
+        let ret = run(&bob, "/deploy").await?;
+        assert_eq!(
+            ret,
+            CommandResponse::Ephemeral("/deploy is not a command".to_string())
+        );
+
+        run(&bob, "/leave").await?;
+        let ret = run(&bob, "/topic hi").await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn run_command_should_respond_in_channel_or_ephemerally() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let input = CreateUser::new("acme", "Alice", "alice@acme.org", "hunter42");
+        let user = User::create(&input, &state.pool).await?;
+        let input = CreateChat::new("general", ChatType::PublicChannel, &[]);
+        let chat = Chat::create(&input, user.ws_id as _, user.id as _, &state.pool).await?;
+
+        let input = CommandInput::parse("/topic launch").unwrap();
+        let ret = run_command(&state, &user, None, chat.id as _, &input).await?;
+        assert_eq!(ret.status(), StatusCode::CREATED);
+        let input = CommandInput::parse("/nope").unwrap();
+        let ret = run_command(&state, &user, None, chat.id as _, &input).await?;
+        assert_eq!(ret.status(), StatusCode::OK);
+
+        // only the in-channel response is a message
+        let messages = Message::list(
+            &ListMessages::default(),
+            chat.id as _,
+            user.id as _,
+            &state.pool,
+        )
+        .await?;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "set the topic: launch");
+        assert_eq!(messages[0].kind, MessageKind::System);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn commands_should_need_the_scope_of_what_they_change() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let input = CreateUser::new("acme", "Alice", "alice@acme.org", "hunter42");
+        let user = User::create(&input, &state.pool).await?;
+        let input = CreateChat::new("general", ChatType::PublicChannel, &[]);
+        let chat = Chat::create(&input, user.ws_id as _, user.id as _, &state.pool).await?;
+        let scopes = TokenScopes(vec![ApiScope::MessageWrite]);
+
+        for content in [
+            "/topic launch",
+            "/invite @bob@acme.org",
+            "/leave",
+            "/mute 2h",
+        ] {
+            let input = CommandInput::parse(content).unwrap();
+            let ret = execute(&state, &user, Some(&scopes), chat.id as _, &input).await;
+            assert!(matches!(ret, Err(AppError::Forbidden(_))), "{}", content);
+        }
+        let input = CommandInput::parse("/remind me in 30m to ship").unwrap();
+        execute(&state, &user, Some(&scopes), chat.id as _, &input).await?;
+        let scopes = TokenScopes(vec![ApiScope::MessageWrite, ApiScope::ChatWrite]);
+        let input = CommandInput::parse("/topic launch").unwrap();
+        execute(&state, &user, Some(&scopes), chat.id as _, &input).await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn ephemeral_message_should_be_cut() {
+        let message = EphemeralMessage::new(1, 2, "é".repeat(MAX_EPHEMERAL_LEN * 5));
+        assert_eq!(message.text.chars().count(), MAX_EPHEMERAL_LEN + 1);
+        assert!(message.text.ends_with('…'));
+        let message = EphemeralMessage::new(1, 2, "ok".to_string());
+        assert_eq!(message.text, "ok");
+    }
+}
@@ -1,10 +1,10 @@
 use axum::{
     http::{Response, StatusCode},
     response::IntoResponse,
-    Json,
 };
-use serde_json::json;
+use chat_core::{FieldError, Problem};
 use thiserror::Error;
+use tracing::error;
 
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -14,6 +14,30 @@ pub enum AppError {
     #[error("email already exists: {0}")]
     EmailAlreadyExists(String),
 
+    #[error("email not verified: {0}")]
+    EmailNotVerified(String),
+
+    #[error("not found: {0}")]
+    NotFound(String),
+
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("conflict: {0}")]
+    Conflict(String),
+
+    #[error("validation failed: {}", join_fields(.0))]
+    Validation(Vec<FieldError>),
+
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("too many failed sign-ins, retry in {0} seconds")]
+    TooManySignins(u64),
+
+    #[error("rate limiter is tracking too many clients")]
+    RateLimiterFull,
+
     #[error("sql error: {0}")]
     SqlxError(#[from] sqlx::Error),
 
@@ -22,18 +46,157 @@ pub enum AppError {
 
     #[error("jwt error: {0}")]
     JwtError(#[from] jwt_simple::Error),
+
+    #[error("smtp error: {0}")]
+    SmtpError(#[from] lettre::transport::smtp::Error),
+
+    #[error("email error: {0}")]
+    EmailError(#[from] lettre::error::Error),
+
+    #[error("crypto error: {0}")]
+    CryptoError(String),
+
+    #[error("oidc error: {0}")]
+    OidcError(String),
+
+    #[error("template error: {0}")]
+    TemplateError(#[from] minijinja::Error),
+
+    #[error("ldap error: {0}")]
+    LdapError(#[from] ldap3::LdapError),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response<axum::body::Body> {
-        let status = match self {
+impl AppError {
+    // a validation error of a single field
+    pub fn invalid(field: &str, message: impl Into<String>) -> Self {
+        Self::Validation(vec![FieldError::new(field, message)])
+    }
+
+    // stable, for clients to switch on. server errors share one code, they are
+    // nothing the client can act on
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Unauthorized => "unauthorized",
+            AppError::EmailAlreadyExists(_) => "email_exists",
+            AppError::EmailNotVerified(_) => "email_not_verified",
+            AppError::NotFound(_) => "not_found",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Conflict(_) => "conflict",
+            AppError::Validation(_) => "validation_failed",
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::TooManySignins(_) => "too_many_signins",
+            AppError::JwtError(_) => "invalid_token",
+            AppError::SmtpError(_) | AppError::OidcError(_) | AppError::LdapError(_) => {
+                "upstream_error"
+            }
+            AppError::RateLimiterFull => "unavailable",
+            AppError::SqlxError(_)
+            | AppError::PasswordHashError(_)
+            | AppError::EmailError(_)
+            | AppError::CryptoError(_)
+            | AppError::TemplateError(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
             AppError::Unauthorized => StatusCode::UNAUTHORIZED,
             AppError::EmailAlreadyExists(_) => StatusCode::CONFLICT,
+            AppError::EmailNotVerified(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            AppError::TooManySignins(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::RateLimiterFull => StatusCode::SERVICE_UNAVAILABLE,
             AppError::SqlxError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::PasswordHashError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::PasswordHashError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::JwtError(_) => StatusCode::FORBIDDEN,
+            AppError::SmtpError(_) => StatusCode::BAD_GATEWAY,
+            AppError::EmailError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::CryptoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::OidcError(_) => StatusCode::BAD_GATEWAY,
+            AppError::TemplateError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::LdapError(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response<axum::body::Body> {
+        let status = self.status();
+        // server errors may contain sql, hosts or secrets, they are only logged
+        let detail = if status.is_server_error() {
+            error!("Request failed with {}: {:?}", self.code(), self);
+            "the server failed to handle the request, report it with the request id".to_string()
+        } else {
+            self.to_string()
         };
 
-        (status, Json(json!({ "error": self.to_string() }))).into_response()
+        let problem = Problem::new(status, self.code(), detail);
+        match self {
+            AppError::Validation(errors) => problem.with_errors(errors),
+            _ => problem,
+        }
+        .into_response()
+    }
+}
+
+fn join_fields(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt as _;
+
+    use super::*;
+
+    async fn problem(e: AppError) -> anyhow::Result<Problem> {
+        let ret =
+            chat_core::scope_request_id("req-1".to_string(), async { e.into_response() }).await;
+        let body = ret.into_body().collect().await?.to_bytes();
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    #[tokio::test]
+    async fn problem_should_hide_server_errors() -> anyhow::Result<()> {
+        let e = AppError::SqlxError(sqlx::Error::Protocol("relation users".to_string()));
+        let ret = problem(e).await?;
+        assert_eq!(ret.status, 500);
+        assert_eq!(ret.code, "internal_error");
+        assert!(!ret.detail.contains("users"));
+        assert_eq!(ret.request_id.as_deref(), Some("req-1"));
+
+        let ret = problem(AppError::NotFound("chat 1".to_string())).await?;
+        assert_eq!(ret.status, 404);
+        assert_eq!(ret.detail, "not found: chat 1");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn problem_should_list_invalid_fields() -> anyhow::Result<()> {
+        let e = AppError::Validation(vec![
+            FieldError::new("name", "can't be empty"),
+            FieldError::new("url", "must be a http url"),
+        ]);
+        assert_eq!(
+            e.to_string(),
+            "validation failed: name: can't be empty, url: must be a http url"
+        );
+
+        let ret = problem(e).await?;
+        assert_eq!(ret.status, 422);
+        assert_eq!(ret.code, "validation_failed");
+        assert_eq!(ret.errors.len(), 2);
+        assert_eq!(ret.errors[1].field, "url");
+
+        Ok(())
     }
 }
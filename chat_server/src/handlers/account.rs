@@ -0,0 +1,246 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use lettre::message::Mailbox;
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    models::{ForgotPassword, ResetPassword, SigninEvent, TokenKind, VerifyEmail},
+    AppError, AppState, User,
+};
+
+use super::Json;
+
+pub(crate) async fn verify_email_handler(
+    State(state): State<AppState>,
+    Json(input): Json<VerifyEmail>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id = TokenKind::VerifyEmail
+        .consume(&input.token, &state.pool)
+        .await?;
+    User::set_email_verified(user_id, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn resend_verification_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = User::find_by_id(user.id as _, &state.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("user {}", user.id)))?;
+    if user.email_verified == Some(true) {
+        return Err(AppError::Conflict("email already verified".to_string()));
+    }
+    spawn_account_email(state, user, TokenKind::VerifyEmail);
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+// always accepted, so the endpoint can't be used to find out who has an account
+pub(crate) async fn forgot_password_handler(
+    State(state): State<AppState>,
+    Json(input): Json<ForgotPassword>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(user) = User::find_by_email(&input.email, &state.pool).await? {
+        let kind = TokenKind::ResetPassword;
+        if kind.issued_recently(user.id as _, &state.pool).await? {
+            warn!("Reset password email to user {} throttled", user.id);
+        } else {
+            spawn_account_email(state, user, kind);
+        }
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub(crate) async fn reset_password_handler(
+    State(state): State<AppState>,
+    Json(input): Json<ResetPassword>,
+) -> Result<impl IntoResponse, AppError> {
+    if input.password.is_empty() {
+        return Err(AppError::invalid("password", "password can't be empty"));
+    }
+
+    User::reset_password(&input.token, &input.password, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// the user's recent password sign-ins, failed ones included
+pub(crate) async fn list_signins_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let events = SigninEvent::list_by_user(user.id as _, &state.pool).await?;
+
+    Ok(Json(events))
+}
+
+// send the email in the background, so the response doesn't wait for the smtp server
+pub(crate) fn spawn_account_email(state: AppState, user: User, kind: TokenKind) {
+    tokio::spawn(async move {
+        if let Err(e) = send_account_email(&state, &user, kind).await {
+            warn!("Failed to send {:?} email to user {}: {}", kind, user.id, e);
+        }
+    });
+}
+
+async fn send_account_email(
+    state: &AppState,
+    user: &User,
+    kind: TokenKind,
+) -> Result<(), AppError> {
+    let (Some(config), Some(mailer)) = (&state.config.email, &state.mailer) else {
+        warn!("Email is not configured, can't send {:?} email", kind);
+        return Ok(());
+    };
+
+    let to = user
+        .email
+        .parse()
+        .map(|email| Mailbox::new(Some(user.fullname.clone()), email))
+        .map_err(|e| AppError::InvalidInput(format!("invalid email {}: {}", user.email, e)))?;
+    let token = kind.issue(user.id as _, &state.pool).await?;
+    let (subject, template, path) = match kind {
+        TokenKind::VerifyEmail => ("Verify your email address", "verify_email", "verify-email"),
+        TokenKind::ResetPassword => ("Reset your password", "reset_password", "reset-password"),
+    };
+    let ctx = json!({
+        "fullname": user.fullname,
+        "link": format!("{}/{}?token={}", config.base_url.trim_end_matches('/'), path, token),
+        "expires_in": kind.ttl().num_hours(),
+    });
+
+    mailer.send(to, subject, template, ctx).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        models::{CreateUser, SigninClient, SigninUser},
+        AppConfig,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify_email_handler() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let input = CreateUser::new("default", "fullname", "email", "password");
+        let user = User::create(&input, &state.pool).await?;
+
+        let user = User::find_by_id(user.id as _, &state.pool).await?.unwrap();
+        assert_eq!(user.email_verified, Some(false));
+
+        let token = TokenKind::VerifyEmail
+            .issue(user.id as _, &state.pool)
+            .await?;
+        let input = VerifyEmail { token };
+        let ret = verify_email_handler(State(state.clone()), Json(input.clone()))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::NO_CONTENT);
+
+        let user = User::find_by_id(user.id as _, &state.pool).await?.unwrap();
+        assert_eq!(user.email_verified, Some(true));
+
+        // the token can't be used twice
+        let ret = verify_email_handler(State(state), Json(input)).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reset_password_handler() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let input = CreateUser::new("default", "fullname", "email", "password");
+        let user = User::create(&input, &state.pool).await?;
+
+        // unknown emails are accepted as well
+        let input = ForgotPassword {
+            email: "nobody".to_string(),
+        };
+        let ret = forgot_password_handler(State(state.clone()), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::ACCEPTED);
+
+        // a used up token is rejected, and the password is left as it was
+        let token = TokenKind::ResetPassword
+            .issue(user.id as _, &state.pool)
+            .await?;
+        TokenKind::ResetPassword
+            .consume(&token, &state.pool)
+            .await?;
+        let input = ResetPassword {
+            token,
+            password: "other password".to_string(),
+        };
+        let ret = reset_password_handler(State(state.clone()), Json(input)).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        let token = TokenKind::ResetPassword
+            .issue(user.id as _, &state.pool)
+            .await?;
+        let input = ResetPassword {
+            token,
+            password: "new password".to_string(),
+        };
+        let ret = reset_password_handler(State(state.clone()), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::NO_CONTENT);
+
+        let client = SigninClient::default();
+        let input = SigninUser::new("email", "password");
+        assert!(
+            User::verify(&input, &client, &state.config.auth.lockout, &state.pool)
+                .await?
+                .is_none()
+        );
+        let input = SigninUser::new("email", "new password");
+        let user = User::verify(&input, &client, &state.config.auth.lockout, &state.pool)
+            .await?
+            .unwrap();
+        assert_eq!(user.email_verified, Some(true));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_forgot_password_should_be_throttled() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let input = CreateUser::new("default", "fullname", "email", "password");
+        let user = User::create(&input, &state.pool).await?;
+
+        let kind = TokenKind::ResetPassword;
+        assert!(!kind.issued_recently(user.id as _, &state.pool).await?);
+        kind.issue(user.id as _, &state.pool).await?;
+        assert!(kind.issued_recently(user.id as _, &state.pool).await?);
+        // other kinds are throttled on their own
+        assert!(
+            !TokenKind::VerifyEmail
+                .issued_recently(user.id as _, &state.pool)
+                .await?
+        );
+
+        // still accepted, no new token is issued
+        let input = ForgotPassword {
+            email: "email".to_string(),
+        };
+        let ret = forgot_password_handler(State(state.clone()), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::ACCEPTED);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_tokens WHERE user_id = $1")
+            .bind(user.id)
+            .fetch_one(&state.pool)
+            .await?;
+        assert_eq!(count, 1);
+
+        Ok(())
+    }
+}
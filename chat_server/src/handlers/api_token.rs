@@ -0,0 +1,49 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{ApiToken, CreateApiToken},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ApiTokenOutput {
+    #[serde(flatten)]
+    api_token: ApiToken,
+    // the plain token, only shown once
+    token: String,
+}
+
+pub(crate) async fn list_api_tokens_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let tokens = ApiToken::list(user.id as _, &state.pool).await?;
+
+    Ok(Json(tokens))
+}
+
+pub(crate) async fn create_api_token_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<CreateApiToken>,
+) -> Result<impl IntoResponse, AppError> {
+    let (api_token, token) = ApiToken::create(&input, user.id as _, &state.pool).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ApiTokenOutput { api_token, token }),
+    ))
+}
+
+pub(crate) async fn revoke_api_token_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    ApiToken::revoke(id, user.id as _, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
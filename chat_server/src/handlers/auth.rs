@@ -1,25 +1,105 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use std::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, State},
+    http::{header, request::Parts, StatusCode},
+    response::IntoResponse,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::{AppError, AppState, User};
+use crate::{
+    middlewares::client_ip,
+    models::{
+        SigninClient, SigninGuard, SigninResult, SigninTwoFactor, TokenKind, UserTotp, Workspace,
+    },
+    utils::ChallengeClaims,
+    AppError, AppState, User,
+};
+
+use super::{spawn_account_email, Json};
 
 #[derive(Debug, Deserialize, Serialize)]
-struct AuthOutput {
+pub(crate) struct AuthOutput {
     token: String,
 }
 
+// the password was right, but a second factor is needed. `enroll` is set when the
+// user has to set up 2fa first, the challenge is then used as the token to do it
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct ChallengeOutput {
+    challenge: String,
+    enroll: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum SigninOutput {
+    Token(AuthOutput),
+    Challenge(ChallengeOutput),
+}
+
 pub(crate) async fn signin_handler(
     State(state): State<AppState>,
+    client: SigninClient,
     Json(input): Json<crate::models::SigninUser>,
 ) -> Result<impl IntoResponse, AppError> {
-    let user = User::verify(&input, &state.pool).await?;
+    let lockout = &state.config.auth.lockout;
+    let guard = SigninGuard::check(&input.email, &client, lockout, &state.pool).await?;
+    let mut user = None;
+    for provider in &state.auth_providers {
+        user = provider.authenticate(&input, &state.pool).await?;
+        if user.is_some() {
+            break;
+        }
+    }
+
+    let Some(user) = user else {
+        guard.failed(SigninResult::BadPassword, &state.pool).await?;
+        return Err(AppError::Unauthorized);
+    };
+    if state.config.auth.require_verified_email && user.email_verified != Some(true) {
+        return Err(AppError::EmailNotVerified(user.email));
+    }
+    let user_id = user.id;
+    let output = signin_output(&state, user).await?;
+    let result = match output {
+        SigninOutput::Token(_) => SigninResult::Success,
+        SigninOutput::Challenge(_) => SigninResult::TwoFactorPending,
+    };
+    guard.succeeded(user_id, result, &state.pool).await?;
+    Ok((StatusCode::OK, Json(output)))
+}
+
+pub(crate) async fn signin_2fa_handler(
+    State(state): State<AppState>,
+    client: SigninClient,
+    Json(input): Json<SigninTwoFactor>,
+) -> Result<impl IntoResponse, AppError> {
+    let ChallengeClaims { id, user, .. } = state.dk.verify_challenge(&input.challenge)?;
+    let lockout = &state.config.auth.lockout;
+    let guard = SigninGuard::check(&user.email, &client, lockout, &state.pool).await?;
+    UserTotp::check_failures(&id, user.id as _, &state.pool).await?;
 
-    match user {
-        Some(user) => {
+    match UserTotp::get(user.id as _, &state.pool).await? {
+        Some(totp)
+            if totp
+                .verify(&user, &input.code, &state.ck, &state.pool)
+                .await? =>
+        {
+            guard
+                .succeeded(user.id, SigninResult::Success, &state.pool)
+                .await?;
             let token = state.ek.sign(user)?;
             Ok((StatusCode::OK, Json(AuthOutput { token })))
         }
-        None => Err(AppError::Unauthorized),
+        _ => {
+            UserTotp::record_failure(&id, user.id as _, &state.pool).await?;
+            guard
+                .failed(SigninResult::BadTwoFactor, &state.pool)
+                .await?;
+            Err(AppError::Unauthorized)
+        }
     }
 }
 
@@ -28,16 +108,65 @@ pub(crate) async fn signup_handler(
     Json(input): Json<crate::models::CreateUser>,
 ) -> Result<impl IntoResponse, AppError> {
     let user = User::create(&input, &state.pool).await?;
+    spawn_account_email(state.clone(), user.clone(), TokenKind::VerifyEmail);
+    // the user signs in once the email is verified, like `signin_handler` requires
+    if state.config.auth.require_verified_email {
+        return Ok((StatusCode::CREATED, Json(user)).into_response());
+    }
+    let output = signin_output(&state, user).await?;
+    Ok((StatusCode::CREATED, Json(output)).into_response())
+}
+
+// users with 2fa, or in a workspace requiring it, get a challenge instead of a token
+pub(crate) async fn signin_output(state: &AppState, user: User) -> Result<SigninOutput, AppError> {
+    let enabled = UserTotp::is_enabled(user.id as _, &state.pool).await?;
+    let enroll = !enabled
+        && Workspace::find_by_id(user.ws_id as _, &state.pool)
+            .await?
+            .is_some_and(|ws| ws.require_2fa);
+
+    if enabled || enroll {
+        let challenge = state
+            .ek
+            .sign_challenge(ChallengeClaims::new(user, enroll))?;
+        return Ok(SigninOutput::Challenge(ChallengeOutput {
+            challenge,
+            enroll,
+        }));
+    }
+
     let token = state.ek.sign(user)?;
-    Ok((StatusCode::CREATED, Json(AuthOutput { token })))
+    Ok(SigninOutput::Token(AuthOutput { token }))
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for SigninClient {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            ip: client_ip(&parts.headers, &parts.extensions, &state.config.rate_limit),
+            user_agent: parts
+                .headers
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string()),
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use http_body_util::BodyExt as _;
+    use tokio::net::TcpListener;
 
     use crate::{
-        models::{CreateUser, SigninUser},
+        config::{AuthProviderKind, LdapConfig},
+        models::{CreateUser, SigninEvent, SigninUser},
+        utils::{start_ldap_server, test_directory},
         AppConfig,
     };
 
@@ -66,7 +195,7 @@ mod tests {
         User::create(&user, &state.pool).await?;
 
         let input = SigninUser::new("email", "password");
-        let ret = signin_handler(State(state), Json(input))
+        let ret = signin_handler(State(state), SigninClient::default(), Json(input))
             .await?
             .into_response();
 
@@ -77,4 +206,254 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_signin_with_2fa() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let user = CreateUser::new("default", "fullname", "test@acme.org", "password");
+        let user = User::create(&user, &state.pool).await?;
+
+        // the workspace requires 2fa, so the user has to enroll first
+        let ws = Workspace::find_by_id(user.ws_id as _, &state.pool)
+            .await?
+            .unwrap();
+        ws.set_require_2fa(true, &state.pool).await?;
+        let input = SigninUser::new("test@acme.org", "password");
+        let ret = signin_handler(State(state.clone()), SigninClient::default(), Json(input))
+            .await?
+            .into_response();
+        let body = ret.into_body().collect().await?.to_bytes();
+        let output = serde_json::from_slice::<ChallengeOutput>(&body)?;
+        assert!(output.enroll);
+        assert!(state.dk.verify(&output.challenge).is_err());
+
+        let setup = UserTotp::enroll(&user, &state.ck, &state.pool).await?;
+        let totp = totp_rs::TOTP::from_url(&setup.uri)?;
+        let codes =
+            UserTotp::enable(&user, &totp.generate_current()?, &state.ck, &state.pool).await?;
+
+        let input = SigninUser::new("test@acme.org", "password");
+        let ret = signin_handler(State(state.clone()), SigninClient::default(), Json(input))
+            .await?
+            .into_response();
+        let body = ret.into_body().collect().await?.to_bytes();
+        let output = serde_json::from_slice::<ChallengeOutput>(&body)?;
+        assert!(!output.enroll);
+
+        let input = SigninTwoFactor {
+            challenge: output.challenge.clone(),
+            code: "abcde-12345".to_string(),
+        };
+        let ret =
+            signin_2fa_handler(State(state.clone()), SigninClient::default(), Json(input)).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized)));
+
+        let input = SigninTwoFactor {
+            challenge: output.challenge.clone(),
+            code: codes[0].clone(),
+        };
+        let ret = signin_2fa_handler(State(state.clone()), SigninClient::default(), Json(input))
+            .await?
+            .into_response();
+        let body = ret.into_body().collect().await?.to_bytes();
+        let token = serde_json::from_slice::<AuthOutput>(&body)?.token;
+        assert_eq!(state.dk.verify(&token)?.id, user.id);
+
+        // the challenge is refused after a few wrong codes, even with a right one
+        for _ in 0..2 {
+            let input = SigninTwoFactor {
+                challenge: output.challenge.clone(),
+                code: "abcde-12345".to_string(),
+            };
+            let ret =
+                signin_2fa_handler(State(state.clone()), SigninClient::default(), Json(input))
+                    .await;
+            assert!(matches!(ret, Err(AppError::Unauthorized)));
+        }
+        let input = SigninTwoFactor {
+            challenge: output.challenge,
+            code: codes[1].clone(),
+        };
+        let ret =
+            signin_2fa_handler(State(state.clone()), SigninClient::default(), Json(input)).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+
+        // the password step is recorded as pending, the sign-in when the code is right
+        let events = SigninEvent::list_by_user(user.id as _, &state.pool).await?;
+        let results: Vec<_> = events.iter().rev().map(|e| e.result).collect();
+        assert_eq!(
+            &results[1..],
+            &[
+                SigninResult::TwoFactorPending,
+                SigninResult::BadTwoFactor,
+                SigninResult::Success,
+                SigninResult::BadTwoFactor,
+                SigninResult::BadTwoFactor,
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_signup_unverified_email() -> anyhow::Result<()> {
+        let mut config = AppConfig::load()?;
+        config.auth.require_verified_email = true;
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+        let input = CreateUser::new("default", "fullname", "email", "password");
+        let ret = signup_handler(State(state), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::CREATED);
+
+        // the account, without a token
+        let body = ret.into_body().collect().await?.to_bytes();
+        let output: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(output["email"], "email");
+        assert!(output.get("token").is_none());
+        assert!(output.get("challenge").is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_signin_unverified_email() -> anyhow::Result<()> {
+        let mut config = AppConfig::load()?;
+        config.auth.require_verified_email = true;
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+        let user = CreateUser::new("default", "fullname", "email", "password");
+        let user = User::create(&user, &state.pool).await?;
+
+        let input = SigninUser::new("email", "password");
+        let ret = signin_handler(State(state.clone()), SigninClient::default(), Json(input)).await;
+        assert!(matches!(ret, Err(AppError::EmailNotVerified(_))));
+
+        User::set_email_verified(user.id as _, &state.pool).await?;
+        let input = SigninUser::new("email", "password");
+        let ret = signin_handler(State(state), SigninClient::default(), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_signin_with_ldap() -> anyhow::Result<()> {
+        let port = start_ldap_server(test_directory()).await?;
+        let (_tdb, state) = AppState::try_new_for_test(ldap_config(port)?).await?;
+
+        let input = SigninUser::new("alice", "wrong");
+        let ret = signin_handler(State(state.clone()), SigninClient::default(), Json(input)).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized)));
+
+        // the directory user is created on first sign-in, and reused afterwards
+        for _ in 0..2 {
+            let input = SigninUser::new("alice", "secret");
+            let ret = signin_handler(State(state.clone()), SigninClient::default(), Json(input))
+                .await?
+                .into_response();
+            let body = ret.into_body().collect().await?.to_bytes();
+            let output = serde_json::from_slice::<AuthOutput>(&body)?;
+            let user = state.dk.verify(&output.token)?;
+            assert_eq!(user.email, "alice@acme.org");
+            assert_eq!(user.fullname, "Alice Chen");
+        }
+        let ws = Workspace::find_by_name("acme", &state.pool).await?.unwrap();
+        let user = User::find_by_email("alice@acme.org", &state.pool)
+            .await?
+            .unwrap();
+        assert_eq!(user.ws_id, ws.id);
+
+        // local users still sign in with their password
+        let user = CreateUser::new("default", "fullname", "email", "password");
+        User::create(&user, &state.pool).await?;
+        let input = SigninUser::new("email", "password");
+        let ret = signin_handler(State(state), SigninClient::default(), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ldap_should_not_take_over_local_accounts() -> anyhow::Result<()> {
+        let port = start_ldap_server(test_directory()).await?;
+        let (_tdb, state) = AppState::try_new_for_test(ldap_config(port)?).await?;
+        let user = CreateUser::new("default", "Alice", "alice@acme.org", "hunter42");
+        let alice = User::create(&user, &state.pool).await?;
+
+        // the directory has an entry with the same mail
+        let input = SigninUser::new("alice", "secret");
+        let ret = signin_handler(State(state.clone()), SigninClient::default(), Json(input)).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized)));
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM identities")
+            .fetch_one(&state.pool)
+            .await?;
+        assert_eq!(count, 0);
+
+        let input = SigninUser::new("alice@acme.org", "hunter42");
+        let ret = signin_handler(State(state.clone()), SigninClient::default(), Json(input))
+            .await?
+            .into_response();
+        let body = ret.into_body().collect().await?.to_bytes();
+        let output = serde_json::from_slice::<AuthOutput>(&body)?;
+        assert_eq!(state.dk.verify(&output.token)?.id, alice.id);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ldap_signin_should_be_locked_out() -> anyhow::Result<()> {
+        // nothing listens on the port
+        let port = TcpListener::bind("127.0.0.1:0").await?.local_addr()?.port();
+        let mut config = ldap_config(port)?;
+        config.auth.providers = vec![AuthProviderKind::Ldap];
+        config.auth.lockout.max_failures = 1;
+        config.auth.lockout.delay_ms = 0;
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+
+        let input = SigninUser::new("alice", "secret");
+        let ret = signin_handler(State(state.clone()), SigninClient::default(), Json(input)).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized)));
+        let input = SigninUser::new("alice", "secret");
+        let ret = signin_handler(State(state), SigninClient::default(), Json(input)).await;
+        assert!(matches!(ret, Err(AppError::TooManySignins(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ldap_errors_should_fall_through_to_the_next_provider() -> anyhow::Result<()> {
+        // nothing listens on the port
+        let port = TcpListener::bind("127.0.0.1:0").await?.local_addr()?.port();
+        let mut config = ldap_config(port)?;
+        config.auth.providers = vec![AuthProviderKind::Ldap, AuthProviderKind::Password];
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+        let user = CreateUser::new("default", "fullname", "email", "password");
+        User::create(&user, &state.pool).await?;
+
+        let input = SigninUser::new("email", "password");
+        let ret = signin_handler(State(state), SigninClient::default(), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    fn ldap_config(port: u16) -> anyhow::Result<AppConfig> {
+        let mut config = AppConfig::load()?;
+        config.auth.providers = vec![AuthProviderKind::Password, AuthProviderKind::Ldap];
+        config.ldap = Some(LdapConfig {
+            url: format!("ldap://127.0.0.1:{}", port),
+            starttls: false,
+            bind_dn: "uid={username},ou=people,dc=acme,dc=org".to_string(),
+            fullname_attr: "cn".to_string(),
+            email_attr: "mail".to_string(),
+            workspace: "acme".to_string(),
+        });
+        Ok(config)
+    }
 }
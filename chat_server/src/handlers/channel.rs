@@ -0,0 +1,124 @@
+use axum::{extract::State, response::IntoResponse, Extension};
+
+use crate::{
+    models::{Chat, ChatType, ListChannels, Workspace},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path, Query};
+
+pub(crate) async fn list_channels_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(input): Query<ListChannels>,
+) -> Result<impl IntoResponse, AppError> {
+    let channels = Chat::list_public_channels(user.ws_id as _, &input, &state.pool).await?;
+
+    Ok(Json(channels))
+}
+
+pub(crate) async fn archive_channel_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = get_managed_channel(id, &user, &state).await?;
+    let chat = chat.set_archived(true, &state.pool).await?;
+
+    Ok(Json(chat))
+}
+
+pub(crate) async fn unarchive_channel_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = get_managed_channel(id, &user, &state).await?;
+    let chat = chat.set_archived(false, &state.pool).await?;
+
+    Ok(Json(chat))
+}
+
+// only the channel creator or the workspace owner can manage a channel
+async fn get_managed_channel(id: u64, user: &User, state: &AppState) -> Result<Chat, AppError> {
+    let chat = match Chat::get_by_id(id, &state.pool).await? {
+        Some(chat) if chat.ws_id == user.ws_id => chat,
+        _ => return Err(AppError::NotFound(format!("channel {}", id))),
+    };
+
+    if !matches!(
+        chat.r#type,
+        ChatType::PublicChannel | ChatType::PrivateChannel
+    ) {
+        return Err(AppError::Forbidden(format!("chat {} is not a channel", id)));
+    }
+
+    if chat.created_by != user.id {
+        let ws = Workspace::find_by_id(user.ws_id as _, &state.pool).await?;
+        if ws.map(|ws| ws.owner_id) != Some(user.id) {
+            return Err(AppError::Forbidden(format!(
+                "user {} can't manage channel {}",
+                user.id, id
+            )));
+        }
+    }
+
+    Ok(chat)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use http_body_util::BodyExt as _;
+
+    use crate::{
+        models::{CreateChat, CreateUser},
+        AppConfig,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_archive_channel_handler() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let input = CreateUser::new("acme", "owner", "owner@acme.org", "password");
+        let owner = User::create(&input, &state.pool).await?;
+        let input = CreateUser::new("acme", "alice", "alice@acme.org", "password");
+        let alice = User::create(&input, &state.pool).await?;
+        let input = CreateUser::new("acme", "bob", "bob@acme.org", "password");
+        let bob = User::create(&input, &state.pool).await?;
+
+        let input = CreateChat::new("general", ChatType::PublicChannel, &[]);
+        let chat = Chat::create(&input, alice.ws_id as _, alice.id as _, &state.pool).await?;
+        let id = chat.id as u64;
+
+        // bob neither created the channel nor owns the workspace
+        let ret = archive_channel_handler(Extension(bob), State(state.clone()), Path(id)).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+
+        let ret = archive_channel_handler(Extension(alice), State(state.clone()), Path(id))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::OK);
+
+        let ret = list_channels_handler(
+            Extension(owner.clone()),
+            State(state.clone()),
+            Query(ListChannels::default()),
+        )
+        .await?
+        .into_response();
+        let body = ret.into_body().collect().await?.to_bytes();
+        let channels = serde_json::from_slice::<Vec<serde_json::Value>>(&body)?;
+        assert!(channels.is_empty());
+
+        let ret = unarchive_channel_handler(Extension(owner), State(state.clone()), Path(id))
+            .await?
+            .into_response();
+        let body = ret.into_body().collect().await?.to_bytes();
+        let chat = serde_json::from_slice::<Chat>(&body)?;
+        assert!(!chat.archived);
+
+        Ok(())
+    }
+}
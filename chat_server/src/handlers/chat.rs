@@ -1,11 +1,24 @@
-use axum::response::IntoResponse;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+
+use crate::{
+    models::{Chat, CreateChat},
+    AppError, AppState, User,
+};
+
+use super::Json;
 
 pub(crate) async fn list_chat_handler() -> impl IntoResponse {
     "list_chat"
 }
 
-pub(crate) async fn create_chat_handler() -> impl IntoResponse {
-    "create_chat"
+pub(crate) async fn create_chat_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<CreateChat>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = Chat::create(&input, user.ws_id as _, user.id as _, &state.pool).await?;
+
+    Ok((StatusCode::CREATED, Json(chat)))
 }
 
 pub(crate) async fn update_chat_handler() -> impl IntoResponse {
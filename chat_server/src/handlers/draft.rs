@@ -0,0 +1,39 @@
+use axum::{extract::State, response::IntoResponse, Extension};
+
+use crate::{
+    models::{Draft, UpsertDraft},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path};
+
+pub(crate) async fn list_drafts_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let drafts = Draft::list(user.id as _, &state.pool).await?;
+
+    Ok(Json(drafts))
+}
+
+pub(crate) async fn get_draft_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let draft = Draft::get(id, user.id as _, &state.pool).await?;
+
+    Ok(Json(draft))
+}
+
+// returns the stored draft, which is not the input when a newer edit exists
+pub(crate) async fn upsert_draft_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<UpsertDraft>,
+) -> Result<impl IntoResponse, AppError> {
+    let draft = Draft::upsert(&input, id, user.id as _, &state.pool).await?;
+
+    Ok(Json(draft))
+}
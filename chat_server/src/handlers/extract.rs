@@ -0,0 +1,196 @@
+use std::error::Error as _;
+
+use axum::{
+    async_trait,
+    extract::{
+        path::ErrorKind,
+        rejection::{JsonRejection, PathRejection},
+        FromRequest, FromRequestParts, Request,
+    },
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::warn;
+
+use crate::AppError;
+
+// the extractors of axum, with rejections which are problems like every other
+// error. input which doesn't deserialize is a validation error of the field
+// serde failed on
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Json<T>(pub T);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Path<T>(pub T);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::Json::from_request(req, state).await {
+            Ok(axum::Json(value)) => Ok(Self(value)),
+            Err(e) => Err(json_error(e)),
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Self(value)),
+            Err(e) => Err(path_error(e)),
+        }
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // axum doesn't track where deserializing the query failed
+        let query = parts.uri.query().unwrap_or_default();
+        let de = serde_urlencoded::Deserializer::new(form_urlencoded::parse(query.as_bytes()));
+        match serde_path_to_error::deserialize(de) {
+            Ok(value) => Ok(Self(value)),
+            Err(e) => Err(field_error(&e.path().to_string(), e.inner())),
+        }
+    }
+}
+
+fn json_error(e: JsonRejection) -> AppError {
+    match e {
+        JsonRejection::JsonDataError(e) => {
+            let source = e.source().and_then(|e| e.source());
+            match source
+                .and_then(|e| e.downcast_ref::<serde_path_to_error::Error<serde_json::Error>>())
+            {
+                Some(e) => field_error(&e.path().to_string(), e.inner()),
+                None => AppError::InvalidInput(e.body_text()),
+            }
+        }
+        e => AppError::InvalidInput(e.body_text()),
+    }
+}
+
+fn path_error(e: PathRejection) -> AppError {
+    match e {
+        PathRejection::FailedToDeserializePathParams(e) => match e.into_kind() {
+            ErrorKind::ParseErrorAtKey {
+                key, expected_type, ..
+            } => AppError::invalid(&key, format!("must be a {}", expected_type)),
+            ErrorKind::ParseErrorAtIndex {
+                index,
+                expected_type,
+                ..
+            } => AppError::invalid(&index.to_string(), format!("must be a {}", expected_type)),
+            ErrorKind::ParseError { expected_type, .. } => {
+                AppError::invalid("path", format!("must be a {}", expected_type))
+            }
+            ErrorKind::InvalidUtf8InPathParam { key } => {
+                AppError::invalid(&key, "must be valid utf-8")
+            }
+            kind => {
+                // the route and the handler don't agree, a bug of the server
+                warn!("Failed to deserialize path params: {}", kind);
+                AppError::InvalidInput(kind.to_string())
+            }
+        },
+        e => AppError::InvalidInput(e.body_text()),
+    }
+}
+
+// the path is "." when the input as a whole has the wrong type
+fn field_error(path: &str, e: &impl std::fmt::Display) -> AppError {
+    let field = if path == "." { "body" } else { path };
+    AppError::invalid(field, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::header};
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Input {
+        #[allow(dead_code)]
+        name: String,
+        #[allow(dead_code)]
+        limit: Option<u32>,
+    }
+
+    fn field(ret: AppError) -> String {
+        match ret {
+            AppError::Validation(errors) => errors[0].field.clone(),
+            e => panic!("expected a validation error, got {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn json_rejection_should_name_the_field() -> anyhow::Result<()> {
+        let req = Request::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"name": 1}"#))?;
+        let ret = Json::<Input>::from_request(req, &()).await.unwrap_err();
+        assert_eq!(field(ret), "name");
+
+        let req = Request::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{"))?;
+        let ret = Json::<Input>::from_request(req, &()).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_rejection_should_name_the_field() -> anyhow::Result<()> {
+        let (mut parts, _) = Request::builder()
+            .uri("/chats?name=a&limit=x")
+            .body(())?
+            .into_parts();
+        let ret = Query::<Input>::from_request_parts(&mut parts, &())
+            .await
+            .unwrap_err();
+        assert_eq!(field(ret), "limit");
+
+        let (mut parts, _) = Request::builder()
+            .uri("/chats?name=a")
+            .body(())?
+            .into_parts();
+        assert!(Query::<Input>::from_request_parts(&mut parts, &())
+            .await
+            .is_ok());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use axum::{extract::State, http::HeaderMap, response::IntoResponse};
+use chat_core::ReadyReport;
+
+use crate::AppState;
+
+pub(crate) async fn metrics_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let token = state.config.server.metrics_token.as_deref();
+    chat_core::render_metrics(&state.pool, token, &headers)
+}
+
+// the database is reachable and migrated, and the server isn't shutting down
+pub(crate) async fn readyz_handler(State(state): State<AppState>) -> impl IntoResponse {
+    ReadyReport::new(&state.readiness, &state.pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::AppConfig;
+
+    #[tokio::test]
+    async fn readyz_handler_should_fail_when_draining() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let ret = readyz_handler(State(state.clone())).await.into_response();
+        assert_eq!(ret.status(), StatusCode::OK);
+
+        state.readiness.drain();
+        let ret = readyz_handler(State(state)).await.into_response();
+        assert_eq!(ret.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        Ok(())
+    }
+}
@@ -1,9 +1,57 @@
-use axum::response::IntoResponse;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension,
+};
 
-pub(crate) async fn list_message_handler() -> impl IntoResponse {
-    "list_message"
+use crate::{
+    commands::{run_command, CommandInput},
+    middlewares::TokenScopes,
+    models::{CreateMessage, ListMessages, MarkRead, Message},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path, Query};
+
+pub(crate) async fn list_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Query(input): Query<ListMessages>,
+) -> Result<impl IntoResponse, AppError> {
+    let messages = Message::list(&input, id, user.id as _, &state.pool).await?;
+
+    Ok(Json(messages))
 }
 
-pub(crate) async fn create_message_handler() -> impl IntoResponse {
-    "create_message"
+pub(crate) async fn create_message_handler(
+    Extension(user): Extension<User>,
+    scopes: Option<Extension<TokenScopes>>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(mut input): Json<CreateMessage>,
+) -> Result<Response, AppError> {
+    if let Some(command) = CommandInput::parse(&input.content) {
+        let scopes = scopes.map(|Extension(v)| v);
+        return run_command(&state, &user, scopes.as_ref(), id, &command).await;
+    }
+    // `//` escapes a message which should start with a slash
+    if let Some(content) = input.content.trim_start().strip_prefix("//") {
+        input.content = format!("/{}", content);
+    }
+    let message = Message::create(&input, id, user.id as _, &state.pool).await?;
+
+    Ok((StatusCode::CREATED, Json(message)).into_response())
+}
+
+pub(crate) async fn mark_read_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<MarkRead>,
+) -> Result<impl IntoResponse, AppError> {
+    Message::mark_read(&input, id, user.id as _, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
 }
@@ -1,15 +1,74 @@
+mod account;
+mod api_token;
 mod auth;
+mod channel;
 mod chat;
+mod draft;
+mod extract;
+mod health;
 mod messages;
+mod oidc;
+mod outgoing_webhook;
+mod poll;
+mod reminder;
+mod scheduled;
+mod setting;
+mod slash_command;
+mod two_factor;
+mod webhook;
 mod workspace;
 
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{
+    http::{StatusCode, Uri},
+    response::IntoResponse,
+};
 
+use crate::AppError;
+
+pub(crate) use account::*;
+pub(crate) use api_token::*;
 pub(crate) use auth::*;
+pub(crate) use channel::*;
 pub(crate) use chat::*;
+pub(crate) use draft::*;
+pub(crate) use extract::{Json, Path, Query};
+pub(crate) use health::*;
 pub(crate) use messages::*;
+pub(crate) use oidc::*;
+pub(crate) use outgoing_webhook::*;
+pub(crate) use poll::*;
+pub(crate) use reminder::*;
+pub(crate) use scheduled::*;
+pub(crate) use setting::*;
+pub(crate) use slash_command::*;
+pub(crate) use two_factor::*;
+pub(crate) use webhook::*;
 pub(crate) use workspace::*;
 
 pub(crate) async fn index_handler() -> impl IntoResponse {
     (StatusCode::OK, "index")
 }
+
+// unmatched routes are a problem like every other error, not an empty 404
+pub(crate) async fn fallback_handler(uri: Uri) -> AppError {
+    AppError::NotFound(format!("no route for {}", uri.path()))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn fallback_should_answer_with_a_problem() -> anyhow::Result<()> {
+        let ret = fallback_handler("/api/nope".parse()?).await.into_response();
+        assert_eq!(ret.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            ret.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+
+        Ok(())
+    }
+}
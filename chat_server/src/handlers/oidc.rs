@@ -0,0 +1,402 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{AppendHeaders, IntoResponse, Redirect},
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{models::OidcLogin, utils::OidcClient, AppError, AppState, User};
+
+use super::{signin_output, Json, Query};
+
+const STATE_COOKIE: &str = "oidc_state";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct OidcCallback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+// redirect the user to the identity provider
+pub(crate) async fn oidc_login_handler(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let client = oidc_client(&state)?;
+    let req = client.auth_request().await?;
+    OidcLogin::create(&req, None, &state.pool).await?;
+    let cookie = state_cookie(&state, &req.state)?;
+
+    Ok((cookie, Redirect::to(&req.url)))
+}
+
+// link an identity to the signed-in user. the client is sent to the returned url
+// like with a login, the callback signs in as the linked user
+pub(crate) async fn oidc_link_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let client = oidc_client(&state)?;
+    let req = client.auth_request().await?;
+    OidcLogin::create(&req, Some(user.id as _), &state.pool).await?;
+    let cookie = state_cookie(&state, &req.state)?;
+
+    Ok((cookie, Json(json!({ "url": req.url }))))
+}
+
+// the identity provider redirects the user back here with an authorization code
+pub(crate) async fn oidc_callback_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(input): Query<OidcCallback>,
+) -> Result<impl IntoResponse, AppError> {
+    let client = oidc_client(&state)?;
+    if let Some(error) = input.error {
+        let description = input.error_description.unwrap_or_default();
+        return Err(AppError::InvalidInput(format!(
+            "sign-in failed: {} {}",
+            error, description
+        )));
+    }
+    let (Some(code), Some(login_state)) = (input.code, input.state) else {
+        return Err(AppError::InvalidInput("missing code or state".to_string()));
+    };
+    // a login started in another browser, e.g. a link an attacker sent to sign the
+    // user in to the attacker's account
+    let started_here = cookie(&headers, STATE_COOKIE)
+        .and_then(|v| state.dk.verify_oidc_state(v).ok())
+        .is_some_and(|v| v == login_state);
+    if !started_here {
+        return Err(AppError::InvalidInput(
+            "the login was not started by this browser".to_string(),
+        ));
+    }
+
+    let login = OidcLogin::take(&login_state, &state.pool)
+        .await?
+        .ok_or_else(|| AppError::InvalidInput("invalid or expired login".to_string()))?;
+    let token = client
+        .exchange_code(&code, &login.code_verifier, &login.nonce)
+        .await?;
+    let workspace = client.config().workspace_for(&token.email);
+    let link_to = login.user_id.map(|id| id as u64);
+    let user = User::from_identity(&token, workspace, link_to, &state.pool).await?;
+    let output = signin_output(&state, user).await?;
+    let clear = format!("{}=; Path=/api/oidc; Max-Age=0", STATE_COOKIE);
+
+    Ok((
+        StatusCode::OK,
+        AppendHeaders([(header::SET_COOKIE, clear)]),
+        Json(output),
+    ))
+}
+
+// the login state, signed so only this server could have set it
+fn state_cookie(
+    state: &AppState,
+    login_state: &str,
+) -> Result<AppendHeaders<[(header::HeaderName, String); 1]>, AppError> {
+    let value = state.ek.sign_oidc_state(login_state)?;
+    let cookie = format!(
+        "{}={}; Path=/api/oidc; Max-Age=600; HttpOnly; Secure; SameSite=Lax",
+        STATE_COOKIE, value
+    );
+    Ok(AppendHeaders([(header::SET_COOKIE, cookie)]))
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|v| v.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v)
+}
+
+fn oidc_client(state: &AppState) -> Result<&OidcClient, AppError> {
+    state
+        .oidc
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("sso is not configured".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use anyhow::Result;
+    use axum::{extract::Form, routing::get, routing::post, Router};
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use http_body_util::BodyExt as _;
+    use jwt_simple::prelude::*;
+    use reqwest::Url;
+    use serde_json::{json, Value};
+    use sha2::{Digest, Sha256};
+    use tokio::net::TcpListener;
+
+    use crate::{config::OidcConfig, models::CreateUser, AppConfig};
+
+    use super::*;
+
+    // a pending authorization: code challenge, nonce and the user's email
+    type Authorization = (String, String, String);
+
+    struct MockIdp {
+        issuer: String,
+        key: RS256KeyPair,
+        codes: Mutex<HashMap<String, Authorization>>,
+    }
+
+    #[tokio::test]
+    async fn test_oidc_login() -> Result<()> {
+        let idp = start_mock_idp().await?;
+        let (_tdb, state) = AppState::try_new_for_test(oidc_config(&idp)?).await?;
+
+        let (headers, callback) = login(&state, &idp, "code1", "alice@acme.org").await?;
+        let ret = oidc_callback_handler(
+            State(state.clone()),
+            headers.clone(),
+            Query(callback.clone()),
+        )
+        .await?
+        .into_response();
+        assert_eq!(ret.status(), StatusCode::OK);
+        let body = ret.into_body().collect().await?.to_bytes();
+        let output: Value = serde_json::from_slice(&body)?;
+        let user = state.dk.verify(output["token"].as_str().unwrap())?;
+        assert_eq!(user.email, "alice@acme.org");
+        let ws = crate::models::Workspace::find_by_id(user.ws_id as _, &state.pool)
+            .await?
+            .unwrap();
+        assert_eq!(ws.name, "acme");
+
+        // a login can't be completed twice
+        let ret = oidc_callback_handler(State(state.clone()), headers, Query(callback)).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        // signing in again uses the linked identity
+        let (headers, callback) = login(&state, &idp, "code2", "alice@acme.org").await?;
+        oidc_callback_handler(State(state.clone()), headers, Query(callback)).await?;
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE email = $1")
+            .bind("alice@acme.org")
+            .fetch_one(&state.pool)
+            .await?;
+        assert_eq!(count, 1);
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM identities")
+            .fetch_one(&state.pool)
+            .await?;
+        assert_eq!(count, 1);
+
+        // users of unknown domains are not provisioned
+        let (headers, callback) = login(&state, &idp, "code3", "eve@evil.com").await?;
+        let ret = oidc_callback_handler(State(state.clone()), headers, Query(callback)).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oidc_login_should_be_bound_to_the_browser() -> Result<()> {
+        let idp = start_mock_idp().await?;
+        let (_tdb, state) = AppState::try_new_for_test(oidc_config(&idp)?).await?;
+
+        // the attacker's login, completed in the victim's browser
+        let (_, callback) = login(&state, &idp, "code1", "alice@acme.org").await?;
+        let (headers, _) = login(&state, &idp, "code2", "alice@acme.org").await?;
+        let ret =
+            oidc_callback_handler(State(state.clone()), headers, Query(callback.clone())).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+        let ret =
+            oidc_callback_handler(State(state.clone()), HeaderMap::new(), Query(callback)).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oidc_login_should_only_link_for_the_signed_in_user() -> Result<()> {
+        let idp = start_mock_idp().await?;
+        let (_tdb, state) = AppState::try_new_for_test(oidc_config(&idp)?).await?;
+        let input = CreateUser::new("acme", "Alice", "alice@acme.org", "hunter42");
+        let alice = User::create(&input, &state.pool).await?;
+
+        // the identity provider vouching for the email isn't enough
+        let (headers, callback) = login(&state, &idp, "code1", "alice@acme.org").await?;
+        let ret = oidc_callback_handler(State(state.clone()), headers, Query(callback)).await;
+        assert!(matches!(ret, Err(AppError::Conflict(_))));
+
+        // alice links it while signed in
+        let ret = oidc_link_handler(Extension(alice.clone()), State(state.clone()))
+            .await?
+            .into_response();
+        let cookie = ret.headers()[header::SET_COOKIE].to_str()?.to_string();
+        let body = ret.into_body().collect().await?.to_bytes();
+        let output: Value = serde_json::from_slice(&body)?;
+        let (headers, callback) = authorize(
+            &idp,
+            &cookie,
+            output["url"].as_str().unwrap(),
+            "code2",
+            "alice@acme.org",
+        )?;
+        let ret = oidc_callback_handler(State(state.clone()), headers, Query(callback))
+            .await?
+            .into_response();
+        let body = ret.into_body().collect().await?.to_bytes();
+        let output: Value = serde_json::from_slice(&body)?;
+        let user = state.dk.verify(output["token"].as_str().unwrap())?;
+        assert_eq!(user.id, alice.id);
+
+        // from then on the identity signs alice in
+        let (headers, callback) = login(&state, &idp, "code3", "alice@acme.org").await?;
+        oidc_callback_handler(State(state.clone()), headers, Query(callback)).await?;
+
+        Ok(())
+    }
+
+    fn oidc_config(idp: &MockIdp) -> Result<AppConfig> {
+        let mut config = AppConfig::load()?;
+        config.oidc = Some(OidcConfig {
+            issuer: idp.issuer.clone(),
+            client_id: "chat".to_string(),
+            client_secret: Some("secret".to_string()),
+            redirect_uri: "http://localhost:6688/api/oidc/callback".to_string(),
+            scopes: vec!["openid".to_string(), "email".to_string()],
+            domains: HashMap::from([("acme.org".to_string(), "acme".to_string())]),
+        });
+        Ok(config)
+    }
+
+    // start a login, and let the user authenticate at the identity provider
+    async fn login(
+        state: &AppState,
+        idp: &MockIdp,
+        code: &str,
+        email: &str,
+    ) -> Result<(HeaderMap, OidcCallback)> {
+        let ret = oidc_login_handler(State(state.clone()))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::SEE_OTHER);
+        let cookie = ret.headers()[header::SET_COOKIE].to_str()?;
+        let location = ret.headers()["location"].to_str()?;
+        authorize(idp, cookie, location, code, email)
+    }
+
+    // the browser keeps the cookie and the user authenticates at the identity provider
+    fn authorize(
+        idp: &MockIdp,
+        cookie: &str,
+        location: &str,
+        code: &str,
+        email: &str,
+    ) -> Result<(HeaderMap, OidcCallback)> {
+        let mut headers = HeaderMap::new();
+        let (cookie, _) = cookie.split_once(';').unwrap_or((cookie, ""));
+        headers.insert(header::COOKIE, cookie.parse()?);
+        let params: HashMap<String, String> = Url::parse(location)?
+            .query_pairs()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(params["client_id"], "chat");
+        assert_eq!(params["code_challenge_method"], "S256");
+
+        idp.codes.lock().unwrap().insert(
+            code.to_string(),
+            (
+                params["code_challenge"].clone(),
+                params["nonce"].clone(),
+                email.to_string(),
+            ),
+        );
+        let callback = OidcCallback {
+            code: Some(code.to_string()),
+            state: Some(params["state"].clone()),
+            ..Default::default()
+        };
+        Ok((headers, callback))
+    }
+
+    async fn start_mock_idp() -> Result<Arc<MockIdp>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let issuer = format!("http://{}", listener.local_addr()?);
+        let idp = Arc::new(MockIdp {
+            issuer,
+            key: RS256KeyPair::generate(2048)?.with_key_id("test"),
+            codes: Mutex::new(HashMap::new()),
+        });
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(idp.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Ok(idp)
+    }
+
+    async fn discovery(State(idp): State<Arc<MockIdp>>) -> Json<Value> {
+        Json(json!({
+            "issuer": idp.issuer,
+            "authorization_endpoint": format!("{}/authorize", idp.issuer),
+            "token_endpoint": format!("{}/token", idp.issuer),
+            "jwks_uri": format!("{}/jwks", idp.issuer),
+        }))
+    }
+
+    async fn jwks(State(idp): State<Arc<MockIdp>>) -> Json<Value> {
+        let pk = idp.key.public_key().to_components();
+        Json(json!({
+            "keys": [{
+                "kty": "RSA",
+                "kid": "test",
+                "alg": "RS256",
+                "n": URL_SAFE_NO_PAD.encode(pk.n),
+                "e": URL_SAFE_NO_PAD.encode(pk.e),
+            }]
+        }))
+    }
+
+    async fn token(
+        State(idp): State<Arc<MockIdp>>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Result<Json<Value>, StatusCode> {
+        let (challenge, nonce, email) = idp
+            .codes
+            .lock()
+            .unwrap()
+            .remove(&form["code"])
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        let verifier_hash =
+            URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+        if verifier_hash != challenge || form["client_secret"] != "secret" {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        let claims = json!({ "email": email, "email_verified": true, "name": "Alice" });
+        let claims = Claims::with_custom_claims(claims, Duration::from_mins(5))
+            .with_issuer(&idp.issuer)
+            .with_audience("chat")
+            .with_subject(format!("sub-{}", email))
+            .with_nonce(nonce);
+        let id_token = idp
+            .key
+            .sign(claims)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        Ok(Json(json!({
+            "access_token": "access",
+            "token_type": "Bearer",
+            "id_token": id_token,
+        })))
+    }
+}
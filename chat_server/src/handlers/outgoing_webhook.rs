@@ -0,0 +1,79 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{CreateOutgoingWebhook, ListDeliveries, OutgoingWebhook, WebhookDelivery},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path, Query};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct OutgoingWebhookOutput {
+    #[serde(flatten)]
+    webhook: OutgoingWebhook,
+    // signs the deliveries, only shown once
+    secret: String,
+}
+
+pub(crate) async fn list_outgoing_webhooks_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let webhooks = OutgoingWebhook::list(&user, &state.pool).await?;
+
+    Ok(Json(webhooks))
+}
+
+pub(crate) async fn create_outgoing_webhook_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<CreateOutgoingWebhook>,
+) -> Result<impl IntoResponse, AppError> {
+    let (webhook, secret) = OutgoingWebhook::create(
+        &input,
+        &user,
+        &state.config.outbound,
+        &state.ck,
+        &state.pool,
+    )
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(OutgoingWebhookOutput { webhook, secret }),
+    ))
+}
+
+pub(crate) async fn delete_outgoing_webhook_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    OutgoingWebhook::delete(id, &user, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn list_webhook_deliveries_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Query(input): Query<ListDeliveries>,
+) -> Result<impl IntoResponse, AppError> {
+    let webhook = OutgoingWebhook::get(id, &user, &state.pool).await?;
+    let deliveries = WebhookDelivery::list(webhook.id as _, &input, &state.pool).await?;
+
+    Ok(Json(deliveries))
+}
+
+pub(crate) async fn redeliver_webhook_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, delivery_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let webhook = OutgoingWebhook::get(id, &user, &state.pool).await?;
+    let delivery = WebhookDelivery::redeliver(delivery_id, webhook.id as _, &state.pool).await?;
+
+    Ok(Json(delivery))
+}
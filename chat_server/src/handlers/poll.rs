@@ -0,0 +1,50 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+
+use crate::{
+    models::{CreatePoll, Poll, VotePoll},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path};
+
+pub(crate) async fn create_poll_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<CreatePoll>,
+) -> Result<impl IntoResponse, AppError> {
+    let message = Poll::create(&input, id, user.id as _, &state.pool).await?;
+
+    Ok((StatusCode::CREATED, Json(message)))
+}
+
+pub(crate) async fn get_poll_results_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, poll_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let results = Poll::results(poll_id, id, user.id as _, &state.pool).await?;
+
+    Ok(Json(results))
+}
+
+pub(crate) async fn vote_poll_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, poll_id)): Path<(u64, u64)>,
+    Json(input): Json<VotePoll>,
+) -> Result<impl IntoResponse, AppError> {
+    let results = Poll::vote(poll_id, id, user.id as _, &input, &state.pool).await?;
+
+    Ok(Json(results))
+}
+
+pub(crate) async fn unvote_poll_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, poll_id, option)): Path<(u64, u64, u32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let results = Poll::unvote(poll_id, id, user.id as _, option, &state.pool).await?;
+
+    Ok(Json(results))
+}
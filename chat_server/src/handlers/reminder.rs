@@ -0,0 +1,37 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+
+use crate::{
+    models::{CreateReminder, Reminder},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path};
+
+pub(crate) async fn list_reminders_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let reminders = Reminder::list(user.id as _, &state.pool).await?;
+
+    Ok(Json(reminders))
+}
+
+pub(crate) async fn create_reminder_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<CreateReminder>,
+) -> Result<impl IntoResponse, AppError> {
+    let reminder = Reminder::create(&input, user.id as _, &state.pool).await?;
+
+    Ok((StatusCode::CREATED, Json(reminder)))
+}
+
+pub(crate) async fn delete_reminder_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    Reminder::delete(id, user.id as _, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
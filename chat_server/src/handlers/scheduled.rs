@@ -0,0 +1,38 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+
+use crate::{
+    models::{CreateScheduledMessage, ScheduledMessage},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path};
+
+pub(crate) async fn list_scheduled_messages_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let scheduled = ScheduledMessage::list(user.id as _, &state.pool).await?;
+
+    Ok(Json(scheduled))
+}
+
+pub(crate) async fn create_scheduled_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<CreateScheduledMessage>,
+) -> Result<impl IntoResponse, AppError> {
+    let scheduled = ScheduledMessage::create(&input, id, user.id as _, &state.pool).await?;
+
+    Ok((StatusCode::CREATED, Json(scheduled)))
+}
+
+pub(crate) async fn delete_scheduled_message_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    ScheduledMessage::delete(id, user.id as _, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
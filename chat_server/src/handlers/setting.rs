@@ -0,0 +1,50 @@
+use axum::{extract::State, response::IntoResponse, Extension};
+
+use crate::{
+    models::{Chat, ChatNotifySetting, UpdateChatNotifySetting, UpdateUserSettings, UserSettings},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path};
+
+pub(crate) async fn get_settings_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let settings = UserSettings::get(user.id as _, &state.pool).await?;
+
+    Ok(Json(settings))
+}
+
+pub(crate) async fn update_settings_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<UpdateUserSettings>,
+) -> Result<impl IntoResponse, AppError> {
+    let settings = UserSettings::update(user.id as _, &input, &state.pool).await?;
+
+    Ok(Json(settings))
+}
+
+pub(crate) async fn get_chat_notify_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    Chat::get_member_chat(id, user.id as _, &state.pool).await?;
+    let setting = ChatNotifySetting::get(user.id as _, id, &state.pool).await?;
+
+    Ok(Json(setting))
+}
+
+pub(crate) async fn update_chat_notify_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<UpdateChatNotifySetting>,
+) -> Result<impl IntoResponse, AppError> {
+    Chat::get_member_chat(id, user.id as _, &state.pool).await?;
+    let setting = ChatNotifySetting::update(user.id as _, id, &input, &state.pool).await?;
+
+    Ok(Json(setting))
+}
@@ -0,0 +1,56 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{CreateSlashCommand, SlashCommand},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SlashCommandOutput {
+    #[serde(flatten)]
+    command: SlashCommand,
+    // signs the requests to the command url, only shown once
+    secret: String,
+}
+
+pub(crate) async fn list_slash_commands_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let commands = SlashCommand::list(user.ws_id as _, &state.pool).await?;
+
+    Ok(Json(commands))
+}
+
+pub(crate) async fn create_slash_command_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<CreateSlashCommand>,
+) -> Result<impl IntoResponse, AppError> {
+    let (command, secret) = SlashCommand::create(
+        &input,
+        &user,
+        &state.config.outbound,
+        &state.ck,
+        &state.pool,
+    )
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(SlashCommandOutput { command, secret }),
+    ))
+}
+
+pub(crate) async fn delete_slash_command_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    SlashCommand::delete(id, &user, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,138 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{DisableTotp, EnableTotp, UserTotp, Workspace},
+    utils::ChallengeClaims,
+    AppError, AppState, User,
+};
+
+use super::Json;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EnableTotpOutput {
+    recovery_codes: Vec<String>,
+    // access token, when 2fa was set up during sign in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+pub(crate) async fn enroll_totp_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let setup = UserTotp::enroll(&user, &state.ck, &state.pool).await?;
+
+    Ok(Json(setup))
+}
+
+pub(crate) async fn enable_totp_handler(
+    Extension(user): Extension<User>,
+    challenge: Option<Extension<ChallengeClaims>>,
+    State(state): State<AppState>,
+    Json(input): Json<EnableTotp>,
+) -> Result<impl IntoResponse, AppError> {
+    let recovery_codes = UserTotp::enable(&user, &input.code, &state.ck, &state.pool).await?;
+    let token = match challenge {
+        Some(_) => Some(state.ek.sign(user)?),
+        None => None,
+    };
+
+    Ok(Json(EnableTotpOutput {
+        recovery_codes,
+        token,
+    }))
+}
+
+pub(crate) async fn disable_totp_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<DisableTotp>,
+) -> Result<impl IntoResponse, AppError> {
+    let ws = Workspace::find_by_id(user.ws_id as _, &state.pool).await?;
+    if ws.is_some_and(|ws| ws.require_2fa) {
+        return Err(AppError::Forbidden(
+            "2fa is required by the workspace".to_string(),
+        ));
+    }
+
+    let totp = UserTotp::get(user.id as _, &state.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("2fa is not enabled".to_string()))?;
+    if !totp
+        .verify(&user, &input.code, &state.ck, &state.pool)
+        .await?
+    {
+        return Err(AppError::InvalidInput(
+            "invalid verification code".to_string(),
+        ));
+    }
+    UserTotp::disable(user.id as _, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt as _;
+    use totp_rs::TOTP;
+
+    use crate::{models::CreateUser, AppConfig};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_totp_handlers() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let input = CreateUser::new("default", "fullname", "test@acme.org", "password");
+        let user = User::create(&input, &state.pool).await?;
+
+        let ret = enroll_totp_handler(Extension(user.clone()), State(state.clone()))
+            .await?
+            .into_response();
+        let body = ret.into_body().collect().await?.to_bytes();
+        let setup: serde_json::Value = serde_json::from_slice(&body)?;
+        let totp = TOTP::from_url(setup["uri"].as_str().unwrap())?;
+
+        let input = EnableTotp {
+            code: totp.generate_current()?,
+        };
+        let ret = enable_totp_handler(
+            Extension(user.clone()),
+            None,
+            State(state.clone()),
+            Json(input),
+        )
+        .await?
+        .into_response();
+        let body = ret.into_body().collect().await?.to_bytes();
+        let output: EnableTotpOutput = serde_json::from_slice(&body)?;
+        assert_eq!(output.recovery_codes.len(), 10);
+        assert!(output.token.is_none());
+
+        // workspace members can't turn 2fa off once it's required
+        let ws = Workspace::find_by_id(user.ws_id as _, &state.pool)
+            .await?
+            .unwrap();
+        ws.set_require_2fa(true, &state.pool).await?;
+        let input = DisableTotp {
+            code: output.recovery_codes[0].clone(),
+        };
+        let ret = disable_totp_handler(
+            Extension(user.clone()),
+            State(state.clone()),
+            Json(input.clone()),
+        )
+        .await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+
+        ws.set_require_2fa(false, &state.pool).await?;
+        let ret = disable_totp_handler(Extension(user.clone()), State(state.clone()), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::NO_CONTENT);
+        assert!(!UserTotp::is_enabled(user.id as _, &state.pool).await?);
+
+        Ok(())
+    }
+}
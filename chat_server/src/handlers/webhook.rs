@@ -0,0 +1,139 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{CreateWebhook, IncomingWebhook, Message, WebhookPayload},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct WebhookOutput {
+    #[serde(flatten)]
+    webhook: IncomingWebhook,
+    // path to post to, the token in it is only shown once
+    url: String,
+}
+
+pub(crate) async fn list_webhooks_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let webhooks = IncomingWebhook::list(id, user.id as _, &state.pool).await?;
+
+    Ok(Json(webhooks))
+}
+
+pub(crate) async fn create_webhook_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<CreateWebhook>,
+) -> Result<impl IntoResponse, AppError> {
+    let (webhook, token) = IncomingWebhook::create(&input, id, user.id as _, &state.pool).await?;
+    let url = format!("/api/hooks/{}", token);
+
+    Ok((StatusCode::CREATED, Json(WebhookOutput { webhook, url })))
+}
+
+pub(crate) async fn delete_webhook_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, webhook_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    IncomingWebhook::delete(webhook_id, id, user.id as _, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// the token in the url authenticates the request, the message is posted by the
+// webhook's bot user
+pub(crate) async fn incoming_webhook_handler(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    Json(payload): Json<WebhookPayload>,
+) -> Result<impl IntoResponse, AppError> {
+    let webhook = IncomingWebhook::find_by_token(&token, &state.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("webhook".to_string()))?;
+
+    let input = payload.into_message()?;
+    let message = Message::create(
+        &input,
+        webhook.chat_id as _,
+        webhook.bot_id as _,
+        &state.pool,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt as _;
+
+    use crate::{
+        models::{ChatType, CreateChat, CreateUser, ListMessages},
+        AppConfig,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn incoming_webhook_handler_should_post_message() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let input = CreateUser::new("acme", "Alice", "alice@acme.org", "hunter42");
+        let user = User::create(&input, &state.pool).await?;
+        let input = CreateChat::new("alerts", ChatType::PrivateChannel, &[]);
+        let chat =
+            crate::models::Chat::create(&input, user.ws_id as _, user.id as _, &state.pool).await?;
+
+        let input = CreateWebhook {
+            name: "Grafana".to_string(),
+        };
+        let ret = create_webhook_handler(
+            Extension(user.clone()),
+            State(state.clone()),
+            Path(chat.id as _),
+            Json(input),
+        )
+        .await?
+        .into_response();
+        assert_eq!(ret.status(), StatusCode::CREATED);
+        let body = ret.into_body().collect().await?.to_bytes();
+        let output = serde_json::from_slice::<WebhookOutput>(&body)?;
+        let token = output.url.trim_start_matches("/api/hooks/").to_string();
+
+        let payload: WebhookPayload = serde_json::from_str(
+            r#"{"text": "disk usage above 90%", "username": "alertmanager"}"#,
+        )?;
+        let ret = incoming_webhook_handler(State(state.clone()), Path(token), Json(payload))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::CREATED);
+
+        let messages = Message::list(
+            &ListMessages::default(),
+            chat.id as _,
+            user.id as _,
+            &state.pool,
+        )
+        .await?;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].sender_id, output.webhook.bot_id);
+        assert_eq!(messages[0].sender_name.as_deref(), Some("alertmanager"));
+
+        let payload = WebhookPayload {
+            text: "hi".to_string(),
+            ..Default::default()
+        };
+        let ret =
+            incoming_webhook_handler(State(state), Path("bad".to_string()), Json(payload)).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+
+        Ok(())
+    }
+}
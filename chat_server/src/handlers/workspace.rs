@@ -1,6 +1,11 @@
-use axum::{extract::State, response::IntoResponse, Extension, Json};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
 
-use crate::{models::Workspace, AppError, AppState, User};
+use crate::{
+    models::{UpdateWorkspace, Workspace},
+    AppError, AppState, User,
+};
+
+use super::{Json, Path};
 
 pub(crate) async fn list_chat_users_handler(
     Extension(user): Extension<User>,
@@ -11,3 +16,94 @@ pub(crate) async fn list_chat_users_handler(
 
     Ok(Json(users))
 }
+
+pub(crate) async fn update_workspace_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<UpdateWorkspace>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut ws = Workspace::find_by_id(user.ws_id as _, &state.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("workspace {}", user.ws_id)))?;
+    if ws.owner_id != user.id {
+        return Err(AppError::Forbidden(
+            "only the workspace owner can change its settings".to_string(),
+        ));
+    }
+
+    if let Some(require) = input.require_2fa {
+        ws = ws.set_require_2fa(require, &state.pool).await?;
+    }
+
+    Ok(Json(ws))
+}
+
+// the workspace owner lifts the lock of a user after too many failed sign-ins
+pub(crate) async fn unlock_user_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let ws = Workspace::find_by_id(user.ws_id as _, &state.pool).await?;
+    if ws.map(|ws| ws.owner_id) != Some(user.id) {
+        return Err(AppError::Forbidden(
+            "only the workspace owner can unlock users".to_string(),
+        ));
+    }
+    match User::find_by_id(id, &state.pool).await? {
+        Some(target) if target.ws_id == user.ws_id => {}
+        _ => return Err(AppError::NotFound(format!("user {}", id))),
+    }
+    User::unlock(id, &state.pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        models::{CreateUser, SigninClient, SigninUser},
+        AppConfig,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unlock_user_handler() -> anyhow::Result<()> {
+        let mut config = AppConfig::load()?;
+        config.auth.lockout.max_failures = 1;
+        config.auth.lockout.delay_ms = 0;
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+        let input = CreateUser::new("acme", "owner", "owner@acme.org", "password");
+        let owner = User::create(&input, &state.pool).await?;
+        let input = CreateUser::new("acme", "alice", "alice@acme.org", "password");
+        let alice = User::create(&input, &state.pool).await?;
+
+        let client = SigninClient::default();
+        let lockout = &state.config.auth.lockout;
+        let input = SigninUser::new("alice@acme.org", "wrong");
+        User::verify(&input, &client, lockout, &state.pool).await?;
+        let input = SigninUser::new("alice@acme.org", "password");
+        let ret = User::verify(&input, &client, lockout, &state.pool).await;
+        assert!(matches!(ret, Err(AppError::TooManySignins(_))));
+
+        // only the owner can unlock
+        let ret = unlock_user_handler(
+            Extension(alice.clone()),
+            State(state.clone()),
+            Path(alice.id as _),
+        )
+        .await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        let ret = unlock_user_handler(Extension(owner), State(state.clone()), Path(alice.id as _))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::NO_CONTENT);
+
+        assert!(User::verify(&input, &client, lockout, &state.pool)
+            .await?
+            .is_some());
+
+        Ok(())
+    }
+}
@@ -1,20 +1,27 @@
+mod commands;
 mod config;
 mod error;
 mod handlers;
 mod middlewares;
 mod models;
 mod utils;
+mod workers;
 
 use anyhow::Context;
 use axum::{
-    middleware::from_fn_with_state,
-    routing::{get, patch, post},
+    handler::Handler,
+    middleware::{from_fn, from_fn_with_state},
+    routing::{delete, get, patch, post},
     Router,
 };
+use chat_core::{healthz_handler, Readiness, Shutdown};
 use handlers::*;
 use middlewares::*;
-use std::{fmt, ops::Deref, sync::Arc};
-use utils::{DecodingKey, EncodingKey};
+use models::{ApiScope, AuthProvider};
+use std::{fmt, ops::Deref, sync::Arc, time::Duration};
+use utils::{
+    outbound_client, CipherKey, DecodingKey, EncodingKey, Mailer, OidcClient, RateLimiter,
+};
 
 pub use config::AppConfig;
 pub use error::AppError;
@@ -30,34 +37,223 @@ pub(crate) struct AppStateInner {
     pub(crate) config: AppConfig,
     pub(crate) dk: DecodingKey,
     pub(crate) ek: EncodingKey,
+    // encrypts secrets stored in the database
+    pub(crate) ck: CipherKey,
     pub(crate) pool: sqlx::PgPool,
+    pub(crate) mailer: Option<Mailer>,
+    pub(crate) oidc: Option<OidcClient>,
+    pub(crate) auth_providers: Vec<AuthProvider>,
+    // for requests to integrations, e.g. webhooks and slash commands, see
+    // `outbound_client`
+    pub(crate) http: reqwest::Client,
+    pub(crate) rate_limiter: RateLimiter,
+    pub(crate) readiness: Readiness,
 }
 
-pub async fn get_router(config: AppConfig) -> Result<Router, AppError> {
+// the router and what to clean up once it's done serving
+pub async fn get_router(config: AppConfig) -> Result<(Router, Shutdown), AppError> {
     let state = AppState::try_new(config).await?;
+    workers::spawn_digest_worker(state.clone());
+    workers::spawn_webhook_worker(state.clone());
+    workers::spawn_scheduler(state.clone());
 
     let api = Router::new()
-        .route("/users", get(list_chat_users_handler))
-        .route("/chat", get(list_chat_handler).post(create_chat_handler))
+        .route(
+            "/users",
+            get(scoped(list_chat_users_handler, ApiScope::UserRead)),
+        )
+        .route(
+            "/chat",
+            get(scoped(list_chat_handler, ApiScope::ChatRead))
+                .post(scoped(create_chat_handler, ApiScope::ChatWrite)),
+        )
         .route(
             "/chat/:id",
-            patch(update_chat_handler).delete(delete_chat_handler),
+            patch(scoped(update_chat_handler, ApiScope::ChatWrite))
+                .delete(scoped(delete_chat_handler, ApiScope::ChatWrite)),
         )
         .route(
             "/chat/:id/messages",
-            get(list_message_handler).post(create_message_handler),
+            get(scoped(list_message_handler, ApiScope::MessageRead)).post(
+                scoped(create_message_handler, ApiScope::MessageWrite).layer(from_fn_with_state(
+                    (state.clone(), RateGroup::Messages),
+                    rate_limit,
+                )),
+            ),
+        )
+        .route(
+            "/chat/:id/read",
+            post(scoped(mark_read_handler, ApiScope::MessageWrite)),
+        )
+        .route(
+            "/chat/:id/polls",
+            post(scoped(create_poll_handler, ApiScope::MessageWrite)),
+        )
+        .route(
+            "/chat/:id/polls/:poll_id",
+            get(scoped(get_poll_results_handler, ApiScope::MessageRead)),
+        )
+        .route(
+            "/chat/:id/polls/:poll_id/votes",
+            post(scoped(vote_poll_handler, ApiScope::MessageWrite)),
+        )
+        .route(
+            "/chat/:id/polls/:poll_id/votes/:option",
+            delete(scoped(unvote_poll_handler, ApiScope::MessageWrite)),
+        )
+        .route(
+            "/chat/:id/draft",
+            get(scoped(get_draft_handler, ApiScope::MessageRead))
+                .put(scoped(upsert_draft_handler, ApiScope::MessageWrite)),
+        )
+        .route(
+            "/drafts",
+            get(scoped(list_drafts_handler, ApiScope::MessageRead)),
+        )
+        .route(
+            "/chat/:id/scheduled",
+            post(scoped(
+                create_scheduled_message_handler,
+                ApiScope::MessageWrite,
+            )),
         )
+        .route(
+            "/scheduled",
+            get(scoped(
+                list_scheduled_messages_handler,
+                ApiScope::MessageRead,
+            )),
+        )
+        .route(
+            "/scheduled/:id",
+            delete(scoped(
+                delete_scheduled_message_handler,
+                ApiScope::MessageWrite,
+            )),
+        )
+        .route(
+            "/reminders",
+            get(scoped(list_reminders_handler, ApiScope::MessageRead))
+                .post(scoped(create_reminder_handler, ApiScope::MessageWrite)),
+        )
+        .route(
+            "/reminders/:id",
+            delete(scoped(delete_reminder_handler, ApiScope::MessageWrite)),
+        )
+        .route(
+            "/chat/:id/webhooks",
+            get(scoped(list_webhooks_handler, ApiScope::ChatRead))
+                .post(scoped(create_webhook_handler, ApiScope::ChatWrite)),
+        )
+        .route(
+            "/chat/:id/webhooks/:webhook_id",
+            delete(scoped(delete_webhook_handler, ApiScope::ChatWrite)),
+        )
+        .route(
+            "/chat/:id/notification",
+            get(scoped(get_chat_notify_handler, ApiScope::ChatRead))
+                .put(scoped(update_chat_notify_handler, ApiScope::ChatWrite)),
+        )
+        .route(
+            "/settings",
+            get(scoped(get_settings_handler, ApiScope::UserRead))
+                .put(scoped(update_settings_handler, ApiScope::UserWrite)),
+        )
+        .route(
+            "/channels",
+            get(scoped(list_channels_handler, ApiScope::ChatRead)),
+        )
+        .route(
+            "/channels/:id/archive",
+            post(scoped(archive_channel_handler, ApiScope::ChatWrite))
+                .delete(scoped(unarchive_channel_handler, ApiScope::ChatWrite)),
+        )
+        .merge(
+            Router::new()
+                .route("/workspace", patch(update_workspace_handler))
+                .route("/users/:id/unlock", post(unlock_user_handler))
+                .route("/signins", get(list_signins_handler))
+                .route("/2fa", delete(disable_totp_handler))
+                .route("/oidc/link", post(oidc_link_handler))
+                .route("/email/verify/resend", post(resend_verification_handler))
+                .route(
+                    "/tokens",
+                    get(list_api_tokens_handler).post(create_api_token_handler),
+                )
+                .route("/tokens/:id", delete(revoke_api_token_handler))
+                .route(
+                    "/webhooks",
+                    get(list_outgoing_webhooks_handler).post(create_outgoing_webhook_handler),
+                )
+                .route("/webhooks/:id", delete(delete_outgoing_webhook_handler))
+                .route(
+                    "/commands",
+                    get(list_slash_commands_handler).post(create_slash_command_handler),
+                )
+                .route("/commands/:id", delete(delete_slash_command_handler))
+                .route(
+                    "/webhooks/:id/deliveries",
+                    get(list_webhook_deliveries_handler),
+                )
+                .route(
+                    "/webhooks/:id/deliveries/:delivery_id/redeliver",
+                    post(redeliver_webhook_handler),
+                )
+                .route_layer(from_fn(require_session)),
+        )
+        .layer(from_fn_with_state(
+            (state.clone(), RateGroup::Api),
+            rate_limit,
+        ))
         .layer(from_fn_with_state(state.clone(), verify_token))
-        // routers don't need verify_token
-        .route("/signin", post(signin_handler))
-        .route("/signup", post(signup_handler));
+        // users who must set up 2fa can do it with their sign-in challenge
+        .merge(
+            Router::new()
+                .route("/2fa/enroll", post(enroll_totp_handler))
+                .route("/2fa/enable", post(enable_totp_handler))
+                .layer(from_fn_with_state(
+                    (state.clone(), RateGroup::Api),
+                    rate_limit,
+                ))
+                .layer(from_fn_with_state(state.clone(), verify_enroll_token)),
+        )
+        // routers don't need verify_token, they are limited by ip
+        .merge(
+            Router::new()
+                .route("/signin", post(signin_handler))
+                .route("/signin/2fa", post(signin_2fa_handler))
+                .route("/signup", post(signup_handler))
+                .route("/hooks/:token", post(incoming_webhook_handler))
+                .route("/oidc/login", get(oidc_login_handler))
+                .route("/oidc/callback", get(oidc_callback_handler))
+                .route("/email/verify", post(verify_email_handler))
+                .route("/password/forgot", post(forgot_password_handler))
+                .route("/password/reset", post(reset_password_handler))
+                .layer(from_fn_with_state(
+                    (state.clone(), RateGroup::Public),
+                    rate_limit,
+                )),
+        );
 
     let app = Router::new()
         .route("/", get(index_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/metrics", get(metrics_handler))
         .nest("/api", api)
-        .with_state(state);
+        .fallback(fallback_handler)
+        .with_state(state.clone());
+
+    Ok((set_layer(app), state.shutdown()))
+}
 
-    Ok(set_layer(app))
+// only api tokens with the scope can use the handler, session tokens always can
+fn scoped<H, T>(handler: H, scope: ApiScope) -> impl Handler<T, AppState>
+where
+    H: Handler<T, AppState>,
+    T: 'static,
+{
+    handler.layer(from_fn_with_state(scope, require_scope))
 }
 
 impl Deref for AppState {
@@ -72,18 +268,39 @@ impl AppState {
     pub async fn try_new(config: AppConfig) -> Result<Self, AppError> {
         let dk = DecodingKey::load(&config.auth.pk).context("load pk failed")?;
         let ek = EncodingKey::load(&config.auth.sk).context("load sk failed")?;
+        let ck = CipherKey::from_config(&config.auth)?;
+        let mailer = config.email.as_ref().map(Mailer::try_new).transpose()?;
+        let oidc = config.oidc.clone().map(OidcClient::new);
+        let auth_providers = AuthProvider::load_all(&config)?;
         let pool = sqlx::PgPool::connect(&config.server.db_url)
             .await
             .context("load pool failed")?;
+        let rate_limiter = RateLimiter::new(&config.rate_limit, pool.clone());
+        let http = outbound_client(&config.outbound);
         Ok(Self {
             inner: Arc::new(AppStateInner {
                 config,
                 dk,
                 ek,
+                ck,
                 pool,
+                mailer,
+                oidc,
+                auth_providers,
+                http,
+                rate_limiter,
+                readiness: Readiness::default(),
             }),
         })
     }
+
+    fn shutdown(&self) -> Shutdown {
+        Shutdown {
+            readiness: self.readiness.clone(),
+            pool: self.pool.clone(),
+            timeout: Duration::from_secs(self.config.server.shutdown_timeout_secs),
+        }
+    }
 }
 
 impl fmt::Debug for AppStateInner {
@@ -104,17 +321,30 @@ impl AppState {
 
         let dk = DecodingKey::load(&config.auth.pk).context("load pk failed")?;
         let ek = EncodingKey::load(&config.auth.sk).context("load sk failed")?;
+        let ck = CipherKey::from_config(&config.auth)?;
+        let mailer = config.email.as_ref().map(Mailer::try_new).transpose()?;
+        let oidc = config.oidc.clone().map(OidcClient::new);
+        let auth_providers = AuthProvider::load_all(&config)?;
 
         let server_url = config.server.db_url.split("/chat").next().unwrap();
         let tdb = TestPg::new(server_url.to_string(), Path::new("../migrations"));
         let pool = tdb.get_pool().await;
+        let rate_limiter = RateLimiter::new(&config.rate_limit, pool.clone());
+        let http = outbound_client(&config.outbound);
 
         let state = Self {
             inner: Arc::new(AppStateInner {
                 config,
                 dk,
                 ek,
+                ck,
                 pool,
+                mailer,
+                oidc,
+                auth_providers,
+                http,
+                rate_limiter,
+                readiness: Readiness::default(),
             }),
         };
         Ok((tdb, state))
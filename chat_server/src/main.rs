@@ -1,22 +1,21 @@
 use anyhow::Result;
 use chat_server::{get_router, AppConfig};
 use tokio::net::TcpListener;
-use tracing::{info, level_filters::LevelFilter};
-use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
+use tracing::info;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let layer = Layer::new().with_filter(LevelFilter::INFO);
-    tracing_subscriber::registry().with(layer).init();
-
-    let config = AppConfig::load()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = AppConfig::load_with_args(&args)?;
+    chat_core::init_tracing("chat_server", &config.tracing)?;
+    chat_core::init_metrics();
     let addr = format!("0.0.0.0:{}", config.server.port);
 
-    let app = get_router(config).await?;
+    let (app, shutdown) = get_router(config).await?;
     let listener = TcpListener::bind(&addr).await?;
     info!("Listening on: {}", addr);
 
-    axum::serve(listener, app.into_make_service()).await?;
+    chat_core::serve(listener, app, shutdown).await?;
 
     Ok(())
 }
@@ -8,49 +8,172 @@ use axum_extra::{
     headers::{authorization::Bearer, Authorization},
     TypedHeader,
 };
+use chat_core::Problem;
 use tracing::warn;
 
-use crate::AppState;
+use crate::{
+    models::{ApiScope, ApiToken, API_TOKEN_PREFIX},
+    AppError, AppState, User,
+};
 
 pub async fn verify_token(State(state): State<AppState>, req: Request, next: Next) -> Response {
     let (mut parts, body) = req.into_parts();
 
-    let req =
+    let token =
         match TypedHeader::<Authorization<Bearer>>::from_request_parts(&mut parts, &state).await {
-            Ok(TypedHeader(Authorization(bearer))) => {
-                let token = bearer.token();
-                match state.dk.verify(token) {
-                    Ok(user) => {
-                        let mut req = Request::from_parts(parts, body);
-                        req.extensions_mut().insert(user);
-                        req
-                    }
-                    Err(e) => {
-                        let msg = format!("Error verifying token: {}", e);
-                        warn!(msg);
-                        return (StatusCode::FORBIDDEN, msg).into_response();
-                    }
-                }
+            Ok(TypedHeader(Authorization(bearer))) => bearer.token().to_string(),
+            Err(e) => {
+                let msg = format!("Error parsing Authorization header: {}", e);
+                warn!(msg);
+                return Problem::new(StatusCode::UNAUTHORIZED, "unauthorized", msg).into_response();
+            }
+        };
+
+    let mut req = Request::from_parts(parts, body);
+    if token.starts_with(API_TOKEN_PREFIX) {
+        match ApiToken::verify(&token, &state.pool).await {
+            Ok(Some((user, scopes))) => {
+                req.extensions_mut().insert(user);
+                req.extensions_mut().insert(TokenScopes(scopes));
+            }
+            Ok(None) => {
+                let msg = "Error verifying token: invalid or expired api token".to_string();
+                warn!(msg);
+                return Problem::new(StatusCode::FORBIDDEN, "invalid_token", msg).into_response();
+            }
+            Err(e) => return e.into_response(),
+        }
+    } else {
+        match verify_session(&state, &token).await {
+            Ok(user) => {
+                req.extensions_mut().insert(user);
             }
+            Err(res) => return res,
+        }
+    }
+
+    if let Some(user) = req.extensions().get::<User>() {
+        chat_core::record_user(user.id, user.ws_id);
+    }
+    next.run(req).await
+}
+
+// the scopes of the api token a request is authenticated with. requests with a
+// session jwt don't have it, and may use every route
+#[derive(Debug, Clone)]
+pub struct TokenScopes(pub Vec<ApiScope>);
+
+impl TokenScopes {
+    // for handlers which do more than the scope of their route covers
+    pub fn check(scopes: Option<&Self>, scope: ApiScope) -> Result<(), AppError> {
+        match scopes {
+            Some(Self(scopes)) if !scopes.contains(&scope) => Err(AppError::Forbidden(format!(
+                "api token is missing the {} scope",
+                scope
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+// per route check, used after verify_token
+pub async fn require_scope(State(scope): State<ApiScope>, req: Request, next: Next) -> Response {
+    if let Some(TokenScopes(scopes)) = req.extensions().get() {
+        if !scopes.contains(&scope) {
+            let msg = format!("api token is missing the {} scope", scope);
+            warn!(msg);
+            return Problem::new(StatusCode::FORBIDDEN, "forbidden", msg).into_response();
+        }
+    }
+
+    next.run(req).await
+}
+
+// routes which manage the account itself can't be used with an api token
+pub async fn require_session(req: Request, next: Next) -> Response {
+    if req.extensions().get::<TokenScopes>().is_some() {
+        let msg = "api tokens can't be used for this route".to_string();
+        warn!(msg);
+        return Problem::new(StatusCode::FORBIDDEN, "forbidden", msg).into_response();
+    }
+
+    next.run(req).await
+}
+
+// like verify_token, but also accepts the sign-in challenge of a user who has to set
+// up 2fa before getting an access token
+pub async fn verify_enroll_token(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let (mut parts, body) = req.into_parts();
+
+    let token =
+        match TypedHeader::<Authorization<Bearer>>::from_request_parts(&mut parts, &state).await {
+            Ok(TypedHeader(Authorization(bearer))) => bearer.token().to_string(),
             Err(e) => {
                 let msg = format!("Error parsing Authorization header: {}", e);
                 warn!(msg);
-                return (StatusCode::UNAUTHORIZED, msg).into_response();
+                return Problem::new(StatusCode::UNAUTHORIZED, "unauthorized", msg).into_response();
             }
         };
 
+    let mut req = Request::from_parts(parts, body);
+    match verify_session(&state, &token).await {
+        Ok(user) => {
+            req.extensions_mut().insert(user);
+        }
+        Err(res) => match state.dk.verify_challenge(&token) {
+            Ok(challenge) if challenge.enroll => {
+                req.extensions_mut().insert(challenge.user.clone());
+                req.extensions_mut().insert(challenge);
+            }
+            _ => return res,
+        },
+    }
+
+    if let Some(user) = req.extensions().get::<User>() {
+        chat_core::record_user(user.id, user.ws_id);
+    }
     next.run(req).await
 }
 
+// the user of an access token, unless a password change revoked the token
+async fn verify_session(state: &AppState, token: &str) -> Result<User, Response> {
+    let (user, issued_at) = state.dk.verify_issued(token).map_err(|e| {
+        let msg = format!("Error verifying token: {}", e);
+        warn!(msg);
+        Problem::new(StatusCode::FORBIDDEN, "invalid_token", msg).into_response()
+    })?;
+    match chat_core::token_revoked(user.id, issued_at, &state.pool).await {
+        Ok(false) => Ok(user),
+        Ok(true) => {
+            let msg = "Error verifying token: revoked by a password change";
+            warn!(msg);
+            Err(Problem::new(StatusCode::FORBIDDEN, "invalid_token", msg).into_response())
+        }
+        Err(e) => Err(AppError::from(e).into_response()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use axum::{
-        body::Body, http::header::AUTHORIZATION, middleware::from_fn_with_state, routing::get,
+        body::Body,
+        handler::Handler,
+        http::header::AUTHORIZATION,
+        middleware::{from_fn, from_fn_with_state},
+        routing::get,
         Router,
     };
     use tower::ServiceExt;
 
-    use crate::{AppConfig, User};
+    use crate::{
+        models::{CreateApiToken, CreateUser},
+        utils::ChallengeClaims,
+        AppConfig, User,
+    };
 
     use super::*;
 
@@ -94,4 +217,108 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn verify_token_should_reject_tokens_from_before_a_password_change() -> anyhow::Result<()>
+    {
+        let config = AppConfig::load()?;
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+        let input = CreateUser::new("acme", "Alice", "alice@acme.org", "hunter42");
+        let user = User::create(&input, &state.pool).await?;
+        let old = state.ek.sign(user.clone())?;
+
+        // the 2fa enrollment takes access tokens as well
+        let app = Router::new()
+            .route("/api", get(handler))
+            .layer(from_fn_with_state(state.clone(), verify_token))
+            .route(
+                "/enroll",
+                get(handler.layer(from_fn_with_state(state.clone(), verify_enroll_token))),
+            )
+            .with_state(state.clone());
+        let request = |uri: &str, token: &str| {
+            Request::builder()
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .uri(uri)
+                .body(Body::empty())
+        };
+
+        for uri in ["/api", "/enroll"] {
+            let res = app.clone().oneshot(request(uri, &old)?).await?;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        // tokens only have the second they were issued
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        User::update_password(user.id as _, "new password", &state.pool).await?;
+        let new = state.ek.sign(user.clone())?;
+        for uri in ["/api", "/enroll"] {
+            let res = app.clone().oneshot(request(uri, &old)?).await?;
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+            let res = app.clone().oneshot(request(uri, &new)?).await?;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        // a sign-in which has to set up 2fa first
+        let challenge = state.ek.sign_challenge(ChallengeClaims::new(user, true))?;
+        let res = app.oneshot(request("/enroll", &challenge)?).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verify_token_should_accept_api_tokens() -> anyhow::Result<()> {
+        let config = AppConfig::load()?;
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+        let input = CreateUser::new("acme", "Alice", "alice@acme.org", "hunter42");
+        let user = User::create(&input, &state.pool).await?;
+        let input = CreateApiToken {
+            name: "script".to_string(),
+            scopes: vec![ApiScope::ChatRead],
+            expires_in_days: Some(30),
+        };
+        let (_, token) = ApiToken::create(&input, user.id as _, &state.pool).await?;
+
+        let app = Router::new()
+            .route(
+                "/read",
+                get(handler.layer(from_fn_with_state(ApiScope::ChatRead, require_scope))),
+            )
+            .route(
+                "/write",
+                get(handler.layer(from_fn_with_state(ApiScope::ChatWrite, require_scope))),
+            )
+            .route("/account", get(handler.layer(from_fn(require_session))))
+            .layer(from_fn_with_state(state.clone(), verify_token))
+            .with_state(state.clone());
+
+        let call = |uri: &str, token: &str| {
+            Request::builder()
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .uri(uri)
+                .body(Body::empty())
+        };
+        let res = app.clone().oneshot(call("/read", &token)?).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app.clone().oneshot(call("/write", &token)?).await?;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = app.clone().oneshot(call("/account", &token)?).await?;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // session tokens aren't limited by scopes
+        let jwt = state.ek.sign(user)?;
+        let res = app.clone().oneshot(call("/write", &jwt)?).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app.clone().oneshot(call("/account", &jwt)?).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app
+            .clone()
+            .oneshot(call("/read", "chat_pat_revoked")?)
+            .await?;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        Ok(())
+    }
 }
@@ -1,19 +1,22 @@
 mod auth;
+mod rate_limit;
 mod request_id;
 mod server_time;
 
 use axum::{middleware::from_fn, Router};
+use chat_core::MakeRequestSpan;
 use request_id::set_request_id;
 use server_time::ServerTimeLayer;
 use tower::ServiceBuilder;
 use tower_http::{
     compression::CompressionLayer,
-    trace::{DefaultMakeSpan, DefaultOnRequest, DefaultOnResponse, TraceLayer},
+    trace::{DefaultOnRequest, DefaultOnResponse, TraceLayer},
     LatencyUnit,
 };
 use tracing::Level;
 
-pub use auth::verify_token;
+pub use auth::{require_scope, require_session, verify_enroll_token, verify_token, TokenScopes};
+pub use rate_limit::{client_ip, rate_limit, RateGroup};
 
 const REQUEST_ID_HEADER: &str = "x-request-id";
 const SERVER_TIMER_HEADER: &str = "x-server-time";
@@ -21,9 +24,11 @@ const SERVER_TIMER_HEADER: &str = "x-server-time";
 pub(crate) fn set_layer(app: Router) -> Router {
     app.layer(
         ServiceBuilder::new()
+            // before the trace layer, so the request id is in the request span
+            .layer(from_fn(set_request_id))
             .layer(
                 TraceLayer::new_for_http()
-                    .make_span_with(DefaultMakeSpan::new().include_headers(true))
+                    .make_span_with(MakeRequestSpan)
                     .on_request(DefaultOnRequest::new().level(Level::INFO))
                     .on_response(
                         DefaultOnResponse::new()
@@ -32,7 +37,6 @@ pub(crate) fn set_layer(app: Router) -> Router {
                     ),
             )
             .layer(CompressionLayer::new().gzip(true).br(true).deflate(true))
-            .layer(from_fn(set_request_id))
             .layer(ServerTimeLayer),
     )
 }
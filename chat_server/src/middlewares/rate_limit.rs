@@ -0,0 +1,258 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{Extensions, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chat_core::Problem;
+use tracing::warn;
+
+use crate::{config::RateLimitConfig, utils::RateDecision, AppState, User};
+
+const LIMIT_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const REMAINING_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const RESET_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+const RETRY_AFTER_HEADER: HeaderName = HeaderName::from_static("retry-after");
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+// routes which share a limit, see `RateLimitConfig`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateGroup {
+    Public,
+    Api,
+    Messages,
+}
+
+// public routes are limited by ip, the others by user, so they must come after
+// verify_token
+pub async fn rate_limit(
+    State((state, group)): State<(AppState, RateGroup)>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let config = &state.config.rate_limit;
+    let limit = match group {
+        RateGroup::Public => &config.public,
+        RateGroup::Api => &config.api,
+        RateGroup::Messages => &config.messages,
+    };
+    let client = match (group, req.extensions().get::<User>()) {
+        (RateGroup::Public, _) | (_, None) => client_ip(req.headers(), req.extensions(), config)
+            .unwrap_or_else(|| "unknown".to_string()),
+        (_, Some(user)) => user.id.to_string(),
+    };
+
+    // an ip address or a user id, the key is well within rate_limits.key
+    let key = format!("{}:{}", group, client);
+    let decision = match state.rate_limiter.take(&key, limit).await {
+        Ok(v) => v,
+        Err(e) if group == RateGroup::Public => {
+            // sign-in and sign-up are what the limit protects, they stay limited
+            warn!("Failed to check rate limit for {}: {}", key, e);
+            return Problem::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "rate_limit_unavailable",
+                "try again later",
+            )
+            .into_response();
+        }
+        Err(e) => {
+            // a broken limiter shouldn't take the api down with it, users are
+            // authenticated there
+            warn!("Failed to check rate limit for {}: {}", key, e);
+            return next.run(req).await;
+        }
+    };
+
+    let mut res = if decision.allowed {
+        next.run(req).await
+    } else {
+        let msg = format!(
+            "rate limit exceeded, retry in {} seconds",
+            decision.retry_after
+        );
+        warn!("{} for {}", msg, key);
+        Problem::new(StatusCode::TOO_MANY_REQUESTS, "rate_limited", msg).into_response()
+    };
+    set_headers(&mut res, &decision);
+    res
+}
+
+// a nested group is more specific, so its headers are kept
+fn set_headers(res: &mut Response, decision: &RateDecision) {
+    let headers = res.headers_mut();
+    if headers.contains_key(LIMIT_HEADER) {
+        return;
+    }
+
+    headers.insert(LIMIT_HEADER, HeaderValue::from(decision.limit));
+    headers.insert(REMAINING_HEADER, HeaderValue::from(decision.remaining));
+    headers.insert(RESET_HEADER, HeaderValue::from(decision.reset));
+    if !decision.allowed {
+        headers.insert(RETRY_AFTER_HEADER, HeaderValue::from(decision.retry_after));
+    }
+}
+
+// the connecting address, or the one the outermost proxy saw with `trust_proxy`.
+// it's always an ip address, whatever the client puts in the header
+pub fn client_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    config: &RateLimitConfig,
+) -> Option<String> {
+    let forwarded = config
+        .trust_proxy
+        .then(|| headers.get(FORWARDED_FOR_HEADER)?.to_str().ok())
+        .flatten()
+        .and_then(|v| {
+            let ips: Vec<&str> = v.split(',').map(str::trim).collect();
+            // fewer addresses than proxies, all of them were added by the proxies
+            let idx = ips.len().saturating_sub(config.proxy_hops as usize);
+            ips[idx].parse::<IpAddr>().ok()
+        });
+    match forwarded {
+        Some(ip) => Some(ip.to_string()),
+        None => extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string()),
+    }
+}
+
+impl fmt::Display for RateGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RateGroup::Public => "public",
+            RateGroup::Api => "api",
+            RateGroup::Messages => "messages",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body, handler::Handler, middleware::from_fn_with_state, routing::get, Router,
+    };
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        config::{RateLimit, RateLimitBackend},
+        AppConfig,
+    };
+
+    async fn handler() -> impl IntoResponse {
+        (StatusCode::OK, "ok")
+    }
+
+    #[tokio::test]
+    async fn rate_limit_should_reject_with_retry_after() -> anyhow::Result<()> {
+        let mut config = AppConfig::load()?;
+        config.rate_limit.public = RateLimit {
+            burst: 2,
+            per_minute: 1,
+        };
+        config.rate_limit.trust_proxy = true;
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+
+        let app = Router::new().route(
+            "/",
+            get(handler.layer(from_fn_with_state(
+                (state.clone(), RateGroup::Public),
+                rate_limit,
+            ))),
+        );
+        // the client makes up the first address to get a fresh bucket every time
+        let spoofed = AtomicU32::new(0);
+        let request = |ip: &str| {
+            let spoofed = spoofed.fetch_add(1, Ordering::Relaxed);
+            Request::builder()
+                .uri("/")
+                .header(FORWARDED_FOR_HEADER, format!("10.0.0.{}, {}", spoofed, ip))
+                .body(Body::empty())
+        };
+
+        let res = app.clone().oneshot(request("1.2.3.4")?).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[LIMIT_HEADER], "2");
+        assert_eq!(res.headers()[REMAINING_HEADER], "1");
+
+        app.clone().oneshot(request("1.2.3.4")?).await?;
+        let res = app.clone().oneshot(request("1.2.3.4")?).await?;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[REMAINING_HEADER], "0");
+        assert_eq!(res.headers()[RETRY_AFTER_HEADER], "60");
+
+        let res = app.oneshot(request("5.6.7.8")?).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[test]
+    fn client_ip_should_skip_proxy_hops() {
+        let mut config = RateLimitConfig {
+            trust_proxy: true,
+            ..Default::default()
+        };
+        let ip = |forwarded: &str, config: &RateLimitConfig| {
+            let mut headers = HeaderMap::new();
+            headers.insert(FORWARDED_FOR_HEADER, forwarded.parse().unwrap());
+            let mut extensions = Extensions::new();
+            extensions.insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 9], 4000))));
+            client_ip(&headers, &extensions, config)
+        };
+
+        assert_eq!(ip("1.1.1.1, 2.2.2.2", &config).as_deref(), Some("2.2.2.2"));
+        // not an address, e.g. a made up key
+        assert_eq!(ip("not an ip", &config).as_deref(), Some("10.0.0.9"));
+        config.proxy_hops = 2;
+        assert_eq!(
+            ip("1.1.1.1, 2.2.2.2, 3.3.3.3", &config).as_deref(),
+            Some("2.2.2.2")
+        );
+        assert_eq!(ip("2.2.2.2", &config).as_deref(), Some("2.2.2.2"));
+        config.trust_proxy = false;
+        assert_eq!(ip("2.2.2.2", &config).as_deref(), Some("10.0.0.9"));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_should_fail_closed_for_public_routes() -> anyhow::Result<()> {
+        let mut config = AppConfig::load()?;
+        config.rate_limit.backend = RateLimitBackend::Postgres;
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+        let app = Router::new()
+            .route(
+                "/public",
+                get(handler.layer(from_fn_with_state(
+                    (state.clone(), RateGroup::Public),
+                    rate_limit,
+                ))),
+            )
+            .route(
+                "/api",
+                get(handler.layer(from_fn_with_state(
+                    (state.clone(), RateGroup::Api),
+                    rate_limit,
+                ))),
+            );
+        // the limiter can't reach the database
+        state.pool.close().await;
+
+        let request = |uri: &str| Request::builder().uri(uri).body(Body::empty());
+        let res = app.clone().oneshot(request("/public")?).await?;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let res = app.oneshot(request("/api")?).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+use chat_core::scope_request_id;
 use tracing::warn;
 
 use super::REQUEST_ID_HEADER;
@@ -22,7 +23,11 @@ pub async fn set_request_id(mut req: Request, next: Next) -> Response {
         }
     };
 
-    let mut res = next.run(req).await;
+    // error responses carry the id, so clients can report it
+    let mut res = match id.as_ref().and_then(|v| v.to_str().ok()) {
+        Some(v) => scope_request_id(v.to_string(), next.run(req)).await,
+        None => next.run(req).await,
+    };
 
     let Some(v) = id else {
         return res;
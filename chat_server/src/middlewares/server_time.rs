@@ -1,5 +1,8 @@
 use super::{REQUEST_ID_HEADER, SERVER_TIMER_HEADER};
-use axum::{extract::Request, response::Response};
+use axum::{
+    extract::{MatchedPath, Request},
+    response::Response,
+};
 use std::{
     future::Future,
     pin::Pin,
@@ -41,10 +44,14 @@ where
 
     fn call(&mut self, request: Request) -> Self::Future {
         let start = Instant::now();
+        let method = request.method().clone();
+        let route = request.extensions().get::<MatchedPath>().cloned();
         let future = self.inner.call(request);
         Box::pin(async move {
             let mut response: Response = future.await?;
-            let elapsed = format!("{}ms", start.elapsed().as_millis());
+            let elapsed = start.elapsed();
+            chat_core::record_request(&method, route.as_ref(), response.status(), elapsed);
+            let elapsed = format!("{}ms", elapsed.as_millis());
             match elapsed.parse() {
                 Ok(v) => {
                     response.headers_mut().insert(SERVER_TIMER_HEADER, v);
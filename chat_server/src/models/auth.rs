@@ -0,0 +1,79 @@
+use sqlx::PgPool;
+use tracing::{instrument, warn};
+
+use crate::{
+    config::{AppConfig, AuthProviderKind},
+    utils::LdapClient,
+    AppError, User,
+};
+
+use super::{ExternalIdentity, SigninUser};
+
+// where the credentials of a sign-in are checked. providers are tried in the
+// configured order, the first one that knows the user wins. the brute-force
+// protection is around all of them, see `SigninGuard`
+pub enum AuthProvider {
+    Password,
+    Ldap(LdapClient),
+}
+
+impl AuthProvider {
+    pub fn load_all(config: &AppConfig) -> Result<Vec<Self>, AppError> {
+        config
+            .auth
+            .providers
+            .iter()
+            .map(|kind| match kind {
+                AuthProviderKind::Password => Ok(Self::Password),
+                AuthProviderKind::Ldap => config
+                    .ldap
+                    .clone()
+                    .map(|ldap| Self::Ldap(LdapClient::new(ldap)))
+                    .ok_or_else(|| {
+                        AppError::InvalidInput("ldap provider needs an ldap config".to_string())
+                    }),
+            })
+            .collect()
+    }
+
+    #[instrument(name = "AuthProvider::authenticate", skip_all)]
+    pub async fn authenticate(
+        &self,
+        input: &SigninUser,
+        pool: &PgPool,
+    ) -> Result<Option<User>, AppError> {
+        match self {
+            Self::Password => User::check_password(input, pool).await,
+            Self::Ldap(client) => {
+                // an unreachable directory shouldn't lock out the users of the next
+                // providers
+                let entry = match client.authenticate(&input.email, &input.password).await {
+                    Ok(Some(entry)) => entry,
+                    Ok(None) => return Ok(None),
+                    Err(e) => {
+                        warn!("Failed to authenticate with ldap: {}", e);
+                        return Ok(None);
+                    }
+                };
+                let identity = ExternalIdentity {
+                    issuer: client.config().url.clone(),
+                    subject: entry.dn,
+                    email: entry.email,
+                    email_verified: true,
+                    name: Some(entry.fullname),
+                };
+                let workspace = Some(client.config().workspace.as_str());
+                match User::from_identity(&identity, workspace, None, pool).await {
+                    Ok(user) => Ok(Some(user)),
+                    // the mail attribute matches an account which isn't from the
+                    // directory, it's never linked to the directory user
+                    Err(AppError::Conflict(e)) => {
+                        warn!("Refused ldap user {}: {}", identity.subject, e);
+                        Ok(None)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        }
+    }
+}
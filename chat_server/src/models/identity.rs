@@ -0,0 +1,163 @@
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::{utils::AuthRequest, AppError, User};
+
+use super::{Identity, OidcLogin};
+
+// a user authenticated by an external provider, e.g. the claims of an oidc id token
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+impl Identity {
+    #[instrument(name = "Identity::find", skip_all)]
+    pub async fn find(
+        issuer: &str,
+        subject: &str,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, AppError> {
+        let identity = sqlx::query_as(
+            r#"
+            SELECT id, user_id, issuer, subject, email, created_at
+            FROM identities
+            WHERE issuer = $1 AND subject = $2
+            "#,
+        )
+        .bind(issuer)
+        .bind(subject)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(identity)
+    }
+
+    #[instrument(name = "Identity::create", skip_all)]
+    pub async fn create(
+        user_id: u64,
+        token: &ExternalIdentity,
+        pool: &PgPool,
+    ) -> Result<Self, AppError> {
+        let identity = sqlx::query_as(
+            r#"
+            INSERT INTO identities (user_id, issuer, subject, email)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, issuer, subject, email, created_at
+            "#,
+        )
+        .bind(user_id as i64)
+        .bind(&token.issuer)
+        .bind(&token.subject)
+        .bind(&token.email)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(identity)
+    }
+}
+
+impl User {
+    // find the user linked to the identity, or provision a new one in the given
+    // workspace. an identity is only linked to an existing account by the user
+    // signed in to it, `link_to`, a matching email isn't enough
+    #[instrument(name = "User::from_identity", skip_all)]
+    pub async fn from_identity(
+        token: &ExternalIdentity,
+        workspace: Option<&str>,
+        link_to: Option<u64>,
+        pool: &PgPool,
+    ) -> Result<Self, AppError> {
+        if let Some(identity) = Identity::find(&token.issuer, &token.subject, pool).await? {
+            if link_to.is_some_and(|id| id != identity.user_id as u64) {
+                return Err(AppError::Conflict(
+                    "the identity is linked to another account".to_string(),
+                ));
+            }
+            return User::find_by_id(identity.user_id as _, pool)
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("user {}", identity.user_id)));
+        }
+
+        if let Some(user_id) = link_to {
+            let user = User::find_by_id(user_id, pool)
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("user {}", user_id)))?;
+            Identity::create(user.id as _, token, pool).await?;
+            return Ok(user);
+        }
+
+        // an unverified email could be used to take over someone else's account
+        if !token.email_verified {
+            return Err(AppError::Forbidden(format!(
+                "email {} is not verified by the identity provider",
+                token.email
+            )));
+        }
+        if User::find_by_email(&token.email, pool).await?.is_some() {
+            return Err(AppError::Conflict(format!(
+                "an account with {} exists, sign in to link it",
+                token.email
+            )));
+        }
+
+        let workspace = workspace
+            .ok_or_else(|| AppError::Forbidden(format!("no workspace for {}", token.email)))?;
+        let fullname = token.name.as_deref().unwrap_or(&token.email);
+        let user = User::create_sso(fullname, &token.email, workspace, pool).await?;
+        Identity::create(user.id as _, token, pool).await?;
+
+        Ok(user)
+    }
+}
+
+impl OidcLogin {
+    #[instrument(name = "OidcLogin::create", skip_all)]
+    pub async fn create(
+        req: &AuthRequest,
+        user_id: Option<u64>,
+        pool: &PgPool,
+    ) -> Result<Self, AppError> {
+        // clean up logins which were never completed
+        sqlx::query("DELETE FROM oidc_logins WHERE created_at < NOW() - INTERVAL '10 minutes'")
+            .execute(pool)
+            .await?;
+
+        let login = sqlx::query_as(
+            r#"
+            INSERT INTO oidc_logins (state, nonce, code_verifier, user_id)
+            VALUES ($1, $2, $3, $4)
+            RETURNING state, nonce, code_verifier, user_id, created_at
+            "#,
+        )
+        .bind(&req.state)
+        .bind(&req.nonce)
+        .bind(&req.code_verifier)
+        .bind(user_id.map(|id| id as i64))
+        .fetch_one(pool)
+        .await?;
+
+        Ok(login)
+    }
+
+    // a login can only be completed once, and within 10 minutes
+    #[instrument(name = "OidcLogin::take", skip_all)]
+    pub async fn take(state: &str, pool: &PgPool) -> Result<Option<Self>, AppError> {
+        let login = sqlx::query_as(
+            r#"
+            DELETE FROM oidc_logins
+            WHERE state = $1 AND created_at > NOW() - INTERVAL '10 minutes'
+            RETURNING state, nonce, code_verifier, user_id, created_at
+            "#,
+        )
+        .bind(state)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(login)
+    }
+}
@@ -1,17 +1,53 @@
+mod api_token;
+mod auth;
+mod chat;
+mod digest;
+mod draft;
+mod identity;
+mod messages;
+mod outgoing_webhook;
+mod poll;
+mod reminder;
+mod scheduled;
+mod setting;
+mod signin;
+mod slash_command;
+mod token;
+mod totp;
 mod user;
+mod webhook;
 mod workspace;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+pub use api_token::{CreateApiToken, API_TOKEN_PREFIX};
+pub use auth::AuthProvider;
+pub use chat::{CreateChat, ListChannels, MAX_TOPIC_LEN};
+pub use digest::Digest;
+pub use draft::UpsertDraft;
+pub use identity::ExternalIdentity;
+pub use messages::{CreateMessage, ListMessages, MarkRead};
+pub use outgoing_webhook::{CreateOutgoingWebhook, ListDeliveries};
+pub use poll::{CreatePoll, Poll, VotePoll};
+pub use reminder::CreateReminder;
+pub use scheduled::CreateScheduledMessage;
+pub use setting::{UpdateChatNotifySetting, UpdateUserSettings};
+pub use signin::{SigninClient, SigninGuard};
+pub use slash_command::CreateSlashCommand;
+pub use token::{ForgotPassword, ResetPassword, VerifyEmail};
+pub use totp::{DisableTotp, EnableTotp, SigninTwoFactor};
 pub use user::{CreateUser, SigninUser};
+pub use webhook::{CreateWebhook, WebhookPayload};
+pub use workspace::UpdateWorkspace;
 
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
 pub struct Workspace {
     pub id: i64,
     pub name: String,
     pub owner_id: i64,
+    pub require_2fa: bool,
     pub created_at: DateTime<Utc>,
 }
 
@@ -24,12 +60,334 @@ pub struct User {
     #[sqlx(default)]
     #[serde(skip)]
     pub password_hash: Option<String>,
+    #[sqlx(default)]
+    #[serde(skip)]
+    pub email_verified: Option<bool>,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub kind: UserKind,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "user_token_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    VerifyEmail,
+    ResetPassword,
+}
+
+#[derive(Debug, Clone, FromRow, PartialEq)]
+pub struct UserTotp {
+    pub user_id: i64,
+    pub secret: Vec<u8>,
+    pub enabled: bool,
+    pub last_used_step: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Identity {
+    pub id: i64,
+    pub user_id: i64,
+    pub issuer: String,
+    pub subject: String,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow, PartialEq)]
+pub struct OidcLogin {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    // the signed-in user linking the identity
+    pub user_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[sqlx(type_name = "api_scope")]
+pub enum ApiScope {
+    #[serde(rename = "user:read")]
+    #[sqlx(rename = "user:read")]
+    UserRead,
+    #[serde(rename = "user:write")]
+    #[sqlx(rename = "user:write")]
+    UserWrite,
+    #[serde(rename = "chat:read")]
+    #[sqlx(rename = "chat:read")]
+    ChatRead,
+    #[serde(rename = "chat:write")]
+    #[sqlx(rename = "chat:write")]
+    ChatWrite,
+    #[serde(rename = "message:read")]
+    #[sqlx(rename = "message:read")]
+    MessageRead,
+    #[serde(rename = "message:write")]
+    #[sqlx(rename = "message:write")]
+    MessageWrite,
+}
+
+// the secret itself is only returned once, when the token is created
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct ApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<ApiScope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// a password sign-in, shown to the user as their recent sign-ins
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct SigninEvent {
+    pub id: i64,
+    pub user_id: Option<i64>,
+    pub email: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub result: SigninResult,
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "signin_result", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum SigninResult {
+    Success,
+    BadPassword,
+    UnknownUser,
+    // the account is locked after too many failures
+    Locked,
+    // the ip is blocked after too many failures
+    IpBlocked,
+    // the password was right, the second factor is still to be checked
+    TwoFactorPending,
+    BadTwoFactor,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "user_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum UserKind {
+    #[default]
+    Human,
+    Bot,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "message_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    #[default]
+    Text,
+    // posted on behalf of the user by the server, e.g. "set the topic: ..."
+    System,
+    Poll,
+    File,
+}
+
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
 pub struct ChatUser {
     pub id: i64,
     pub fullname: String,
     pub email: String,
+    pub kind: UserKind,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct IncomingWebhook {
+    pub id: i64,
+    pub chat_id: i64,
+    pub bot_id: i64,
+    pub name: String,
+    pub created_by: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "chat_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ChatType {
+    Single,
+    Group,
+    PrivateChannel,
+    PublicChannel,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[sqlx(type_name = "webhook_event")]
+pub enum WebhookEvent {
+    #[serde(rename = "message.created")]
+    #[sqlx(rename = "message.created")]
+    MessageCreated,
+    #[serde(rename = "chat.created")]
+    #[sqlx(rename = "chat.created")]
+    ChatCreated,
+    #[serde(rename = "member.joined")]
+    #[sqlx(rename = "member.joined")]
+    MemberJoined,
+}
+
+// a webhook without chat gets the events of the whole workspace
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct OutgoingWebhook {
+    pub id: i64,
+    pub ws_id: i64,
+    pub chat_id: Option<i64>,
+    pub url: String,
+    #[serde(skip)]
+    pub secret: Vec<u8>,
+    pub events: Vec<WebhookEvent>,
+    pub created_by: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "webhook_delivery_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Succeeded,
+    Dead,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event: WebhookEvent,
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    // http status of the last attempt, if there was a response
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// a custom slash command, the secret signs the callbacks
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct SlashCommand {
+    pub id: i64,
+    pub ws_id: i64,
+    pub name: String,
+    pub url: String,
+    pub description: String,
+    #[serde(skip)]
+    pub secret: Vec<u8>,
+    pub created_by: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Reminder {
+    pub id: i64,
+    pub user_id: i64,
+    pub chat_id: i64,
+    // set when the reminder is about a message
+    pub message_id: Option<i64>,
+    pub text: String,
+    pub remind_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// an empty content is a cleared draft
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Draft {
+    pub user_id: i64,
+    pub chat_id: i64,
+    pub content: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+// a message the scheduler posts at send_at, message_id or error is set once it's sent
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct ScheduledMessage {
+    pub id: i64,
+    pub chat_id: i64,
+    pub sender_id: i64,
+    pub content: String,
+    pub images: Vec<String>,
+    pub mentions: Vec<i64>,
+    pub send_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub message_id: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Chat {
+    pub id: i64,
+    pub ws_id: i64,
+    pub name: String,
+    pub r#type: ChatType,
+    pub members: Vec<i64>,
+    pub topic: Option<String>,
+    pub description: Option<String>,
+    pub archived: bool,
+    pub created_by: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Message {
+    pub id: i64,
+    pub chat_id: i64,
+    pub sender_id: i64,
+    pub content: String,
+    pub images: Vec<String>,
+    pub mentions: Vec<i64>,
+    // shown instead of the sender's name, set by integrations
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender_name: Option<String>,
+    pub kind: MessageKind,
+    // depends on the kind, e.g. the options of a poll
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "notify_level", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyLevel {
+    #[default]
+    All,
+    Mentions,
+    None,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct ChatNotifySetting {
+    pub user_id: i64,
+    pub chat_id: i64,
+    pub level: NotifyLevel,
+    pub muted_until: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct UserSettings {
+    pub user_id: i64,
+    pub dnd_enabled: bool,
+    pub dnd_start: NaiveTime,
+    pub dnd_end: NaiveTime,
+    pub timezone: String,
+    pub digest_enabled: bool,
+    pub digest_delay: Option<i32>,
+    pub updated_at: DateTime<Utc>,
 }
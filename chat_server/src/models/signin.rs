@@ -0,0 +1,244 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tracing::{instrument, warn};
+
+use crate::{config::LockoutConfig, AppError};
+
+use super::{SigninEvent, SigninResult};
+
+// the user's recent sign-ins which are listed
+const RECENT_SIGNINS: i64 = 50;
+// failures of unknown emails are forgotten after a day without any
+const UNKNOWN_SIGNINS_TTL_SECS: f64 = 24.0 * 3600.0;
+
+// where a sign-in comes from, both are unknown when the server can't tell
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SigninClient {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+// brute-force protection of a sign-in, whatever checks the credentials: a failure
+// is answered later the more failures came before it, too many failures from the
+// ip block it and too many in a row lock the email for a while. emails without an
+// account are locked the same way, so the answers don't tell which have one
+pub struct SigninGuard<'a> {
+    email: &'a str,
+    client: &'a SigninClient,
+    lockout: &'a LockoutConfig,
+    // the account of the email, if there is one
+    user_id: Option<i64>,
+    ip_failures: u32,
+}
+
+impl<'a> SigninGuard<'a> {
+    // refuses the sign-in when the ip is blocked or the email locked
+    #[instrument(name = "SigninGuard::check", skip_all)]
+    pub async fn check(
+        email: &'a str,
+        client: &'a SigninClient,
+        lockout: &'a LockoutConfig,
+        pool: &PgPool,
+    ) -> Result<Self, AppError> {
+        let ip_failures = match &client.ip {
+            Some(ip) => SigninEvent::count_ip_failures(ip, lockout.ip_window_secs, pool).await?,
+            None => 0,
+        };
+        if ip_failures >= lockout.ip_max_failures {
+            SigninEvent::record(None, email, client, SigninResult::IpBlocked, pool).await?;
+            return Err(AppError::TooManySignins(lockout.ip_window_secs));
+        }
+
+        let account: Option<(i64, Option<DateTime<Utc>>)> =
+            sqlx::query_as("SELECT id, locked_until FROM users WHERE email = $1")
+                .bind(email)
+                .fetch_optional(pool)
+                .await?;
+        let (user_id, locked_until) = match account {
+            Some((id, locked_until)) => (Some(id), locked_until),
+            None => {
+                let locked_until: Option<Option<DateTime<Utc>>> =
+                    sqlx::query_scalar("SELECT locked_until FROM unknown_signins WHERE email = $1")
+                        .bind(truncate(email, 64))
+                        .fetch_optional(pool)
+                        .await?;
+                (None, locked_until.flatten())
+            }
+        };
+        if let Some(locked_until) = locked_until.filter(|v| *v > Utc::now()) {
+            SigninEvent::record(user_id, email, client, SigninResult::Locked, pool).await?;
+            let secs = (locked_until - Utc::now()).num_seconds().max(1);
+            return Err(AppError::TooManySignins(secs as u64));
+        }
+
+        Ok(Self {
+            email,
+            client,
+            lockout,
+            user_id,
+            ip_failures,
+        })
+    }
+
+    // the credentials were wrong, `result` is what was wrong for an account
+    #[instrument(name = "SigninGuard::failed", skip_all)]
+    pub async fn failed(self, result: SigninResult, pool: &PgPool) -> Result<(), AppError> {
+        // the lock is set again by every failure once the limit is reached, so
+        // after a lock there is just one more try
+        let (failures, result): (i32, _) = match self.user_id {
+            Some(id) => {
+                let failures = sqlx::query_scalar(
+                    r#"
+                    UPDATE users
+                    SET failed_signins = failed_signins + 1,
+                        locked_until = CASE
+                            WHEN failed_signins + 1 >= $2 THEN NOW() + make_interval(secs => $3)
+                            ELSE locked_until
+                        END
+                    WHERE id = $1
+                    RETURNING failed_signins
+                    "#,
+                )
+                .bind(id)
+                .bind(self.lockout.max_failures as i32)
+                .bind(self.lockout.lock_secs as f64)
+                .fetch_one(pool)
+                .await?;
+                (failures, result)
+            }
+            None => {
+                sqlx::query(
+                    "DELETE FROM unknown_signins WHERE updated_at < NOW() - make_interval(secs => $1)",
+                )
+                .bind(UNKNOWN_SIGNINS_TTL_SECS)
+                .execute(pool)
+                .await?;
+                let failures = sqlx::query_scalar(
+                    r#"
+                    INSERT INTO unknown_signins (email, failures, locked_until)
+                    VALUES ($1, 1, CASE WHEN 1 >= $2 THEN NOW() + make_interval(secs => $3) END)
+                    ON CONFLICT (email) DO UPDATE
+                    SET failures = unknown_signins.failures + 1,
+                        locked_until = CASE
+                            WHEN unknown_signins.failures + 1 >= $2
+                                THEN NOW() + make_interval(secs => $3)
+                            ELSE unknown_signins.locked_until
+                        END,
+                        updated_at = CURRENT_TIMESTAMP
+                    RETURNING failures
+                    "#,
+                )
+                .bind(truncate(self.email, 64))
+                .bind(self.lockout.max_failures as i32)
+                .bind(self.lockout.lock_secs as f64)
+                .fetch_one(pool)
+                .await?;
+                (failures, SigninResult::UnknownUser)
+            }
+        };
+        SigninEvent::record(self.user_id, self.email, self.client, result, pool).await?;
+        if let (Some(id), true) = (self.user_id, failures as u32 >= self.lockout.max_failures) {
+            warn!("Locked user {} after {} failed sign-ins", id, failures);
+        }
+        let failures = (failures as u32).max(self.ip_failures + 1);
+        tokio::time::sleep(self.lockout.delay(failures)).await;
+        Ok(())
+    }
+
+    // the credentials of the user were right. the failures are only reset once
+    // the sign-in is complete, a second factor is still to be checked otherwise
+    #[instrument(name = "SigninGuard::succeeded", skip_all)]
+    pub async fn succeeded(
+        self,
+        user_id: i64,
+        result: SigninResult,
+        pool: &PgPool,
+    ) -> Result<(), AppError> {
+        if result == SigninResult::Success {
+            sqlx::query("UPDATE users SET failed_signins = 0, locked_until = NULL WHERE id = $1")
+                .bind(user_id)
+                .execute(pool)
+                .await?;
+            // a directory user signs in with a name which isn't their email
+            sqlx::query("DELETE FROM unknown_signins WHERE email = $1")
+                .bind(truncate(self.email, 64))
+                .execute(pool)
+                .await?;
+        }
+        SigninEvent::record(Some(user_id), self.email, self.client, result, pool).await
+    }
+}
+
+impl SigninEvent {
+    #[instrument(name = "SigninEvent::record", skip_all)]
+    pub async fn record(
+        user_id: Option<i64>,
+        email: &str,
+        client: &SigninClient,
+        result: SigninResult,
+        pool: &PgPool,
+    ) -> Result<(), AppError> {
+        // the input is whatever the client sent, keep it within the columns
+        sqlx::query(
+            r#"
+            INSERT INTO signin_events (user_id, email, ip, user_agent, result)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(user_id)
+        .bind(truncate(email, 64))
+        .bind(client.ip.as_deref().map(|v| truncate(v, 64)))
+        .bind(client.user_agent.as_deref().map(|v| truncate(v, 256)))
+        .bind(result)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(name = "SigninEvent::list_by_user", skip_all)]
+    pub async fn list_by_user(user_id: u64, pool: &PgPool) -> Result<Vec<Self>, AppError> {
+        let events = sqlx::query_as(
+            r#"
+            SELECT id, user_id, email, ip, user_agent, result, created_at
+            FROM signin_events
+            WHERE user_id = $1
+            ORDER BY created_at DESC, id DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(user_id as i64)
+        .bind(RECENT_SIGNINS)
+        .fetch_all(pool)
+        .await?;
+        Ok(events)
+    }
+
+    // wrong passwords, 2fa codes and unknown emails from the ip, for any account
+    #[instrument(name = "SigninEvent::count_ip_failures", skip_all)]
+    pub async fn count_ip_failures(
+        ip: &str,
+        window_secs: u64,
+        pool: &PgPool,
+    ) -> Result<u32, AppError> {
+        let (count,): (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*)
+            FROM signin_events
+            WHERE ip = $1 AND result IN ('bad_password', 'bad_two_factor', 'unknown_user')
+              AND created_at > NOW() - make_interval(secs => $2)
+            "#,
+        )
+        .bind(truncate(ip, 64))
+        .bind(window_secs as f64)
+        .fetch_one(pool)
+        .await?;
+        Ok(count as u32)
+    }
+}
+
+fn truncate(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::{
+    config::OutboundConfig,
+    utils::{check_url, CipherKey},
+    AppError, User,
+};
+
+use super::{token::random_token, SlashCommand, Workspace};
+
+// custom commands can't shadow these
+pub const BUILTIN_COMMANDS: &[&str] = &["topic", "invite", "leave", "mute", "remind"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateSlashCommand {
+    // without the leading slash, e.g. "deploy"
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl SlashCommand {
+    // only the workspace owner can add commands. the signing secret is returned
+    // next to the command, it can't be retrieved later
+    #[instrument(name = "SlashCommand::create", skip_all)]
+    pub async fn create(
+        input: &CreateSlashCommand,
+        user: &User,
+        outbound: &OutboundConfig,
+        key: &CipherKey,
+        pool: &PgPool,
+    ) -> Result<(Self, String), AppError> {
+        let name = input.name.trim_start_matches('/').to_lowercase();
+        let valid_name = (1..=32).contains(&name.len())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(AppError::invalid(
+                "name",
+                "command name must be 1 to 32 letters, digits, '-' or '_'",
+            ));
+        }
+        if BUILTIN_COMMANDS.contains(&name.as_str()) {
+            return Err(AppError::invalid(
+                "name",
+                format!("/{} is a built-in command", name),
+            ));
+        }
+        let url = check_url(&input.url, outbound).map_err(|e| AppError::invalid("url", e))?;
+        if input.description.chars().count() > 256 {
+            return Err(AppError::invalid(
+                "description",
+                "command description can't be longer than 256 characters",
+            ));
+        }
+        ensure_owner(user, pool).await?;
+        if Self::find_by_name(user.ws_id as _, &name, pool)
+            .await?
+            .is_some()
+        {
+            return Err(AppError::Conflict(format!("/{} already exists", name)));
+        }
+
+        let secret = random_token();
+        let command = sqlx::query_as(
+            r#"
+            INSERT INTO slash_commands (ws_id, name, url, description, secret, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, ws_id, name, url, description, secret, created_by, created_at
+            "#,
+        )
+        .bind(user.ws_id)
+        .bind(&name)
+        .bind(url.as_str())
+        .bind(&input.description)
+        .bind(key.encrypt(secret.as_bytes())?)
+        .bind(user.id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok((command, secret))
+    }
+
+    #[instrument(name = "SlashCommand::list", skip_all)]
+    pub async fn list(ws_id: u64, pool: &PgPool) -> Result<Vec<Self>, AppError> {
+        let commands = sqlx::query_as(
+            r#"
+            SELECT id, ws_id, name, url, description, secret, created_by, created_at
+            FROM slash_commands
+            WHERE ws_id = $1
+            ORDER BY name
+            "#,
+        )
+        .bind(ws_id as i64)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(commands)
+    }
+
+    #[instrument(name = "SlashCommand::find_by_name", skip_all)]
+    pub async fn find_by_name(
+        ws_id: u64,
+        name: &str,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, AppError> {
+        let command = sqlx::query_as(
+            r#"
+            SELECT id, ws_id, name, url, description, secret, created_by, created_at
+            FROM slash_commands
+            WHERE ws_id = $1 AND name = $2
+            "#,
+        )
+        .bind(ws_id as i64)
+        .bind(name)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(command)
+    }
+
+    #[instrument(name = "SlashCommand::delete", skip_all)]
+    pub async fn delete(id: u64, user: &User, pool: &PgPool) -> Result<(), AppError> {
+        ensure_owner(user, pool).await?;
+        let ret = sqlx::query("DELETE FROM slash_commands WHERE id = $1 AND ws_id = $2")
+            .bind(id as i64)
+            .bind(user.ws_id)
+            .execute(pool)
+            .await?;
+        if ret.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("command {}", id)));
+        }
+
+        Ok(())
+    }
+
+    pub fn signing_secret(&self, key: &CipherKey) -> Result<Vec<u8>, AppError> {
+        key.decrypt(&self.secret)
+    }
+}
+
+async fn ensure_owner(user: &User, pool: &PgPool) -> Result<(), AppError> {
+    let ws = Workspace::find_by_id(user.ws_id as _, pool).await?;
+    if ws.map(|ws| ws.owner_id) != Some(user.id) {
+        return Err(AppError::Forbidden(
+            "only the workspace owner can manage slash commands".to_string(),
+        ));
+    }
+
+    Ok(())
+}
@@ -1,16 +1,24 @@
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use tracing::instrument;
 
 use crate::AppError;
 
 use super::{ChatUser, Workspace};
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateWorkspace {
+    pub require_2fa: Option<bool>,
+}
+
 impl Workspace {
+    #[instrument(name = "Workspace::create", skip_all)]
     pub async fn create(name: &str, user_id: u64, pool: &PgPool) -> Result<Self, AppError> {
         let ws = sqlx::query_as(
             r#"
             INSERT INTO workspaces (name, owner_id)
             VALUES ($1, $2)
-            RETURNING id, name, owner_id, created_at
+            RETURNING id, name, owner_id, require_2fa, created_at
             "#,
         )
         .bind(name)
@@ -21,13 +29,14 @@ impl Workspace {
         Ok(ws)
     }
 
+    #[instrument(name = "Workspace::update_owner", skip_all)]
     pub async fn update_owner(&self, owner_id: u64, pool: &PgPool) -> Result<Self, AppError> {
         let ws = sqlx::query_as(
             r#"
             UPDATE workspaces
             SET owner_id = $1
             WHERE id = $2 and (SELECT ws_id FROM users WHERE id = $1) = $2
-            RETURNING id, name, owner_id, created_at
+            RETURNING id, name, owner_id, require_2fa, created_at
             "#,
         )
         .bind(owner_id as i64)
@@ -38,10 +47,11 @@ impl Workspace {
         Ok(ws)
     }
 
+    #[instrument(name = "Workspace::find_by_name", skip_all)]
     pub async fn find_by_name(name: &str, pool: &PgPool) -> Result<Option<Self>, AppError> {
         let ws = sqlx::query_as(
             r#"
-            SELECT id, name, owner_id, created_at
+            SELECT id, name, owner_id, require_2fa, created_at
             FROM workspaces
             WHERE name = $1
             "#,
@@ -53,11 +63,11 @@ impl Workspace {
         Ok(ws)
     }
 
-    #[allow(dead_code)]
+    #[instrument(name = "Workspace::find_by_id", skip_all)]
     pub async fn find_by_id(id: u64, pool: &PgPool) -> Result<Option<Self>, AppError> {
         let ws = sqlx::query_as(
             r#"
-            SELECT id, name, owner_id, created_at
+            SELECT id, name, owner_id, require_2fa, created_at
             FROM workspaces
             WHERE id = $1
             "#,
@@ -69,11 +79,30 @@ impl Workspace {
         Ok(ws)
     }
 
+    #[instrument(name = "Workspace::set_require_2fa", skip_all)]
+    pub async fn set_require_2fa(&self, require: bool, pool: &PgPool) -> Result<Self, AppError> {
+        let ws = sqlx::query_as(
+            r#"
+            UPDATE workspaces
+            SET require_2fa = $1
+            WHERE id = $2
+            RETURNING id, name, owner_id, require_2fa, created_at
+            "#,
+        )
+        .bind(require)
+        .bind(self.id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(ws)
+    }
+
     #[allow(dead_code)]
+    #[instrument(name = "Workspace::fetch_all_chat_users", skip_all)]
     pub async fn fetch_all_chat_users(id: u64, pool: &PgPool) -> Result<Vec<ChatUser>, AppError> {
         let users = sqlx::query_as(
             r#"
-            SELECT id, fullname, email
+            SELECT id, fullname, email, kind
             FROM users
             WHERE ws_id = $1 order by id
             "#,
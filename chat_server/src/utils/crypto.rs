@@ -0,0 +1,97 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{config::AuthConfig, AppError};
+
+const NONCE_LEN: usize = 12;
+
+// AES-256-GCM key to encrypt secrets at rest, the nonce is stored in front of the ciphertext
+pub struct CipherKey(Aes256Gcm);
+
+impl CipherKey {
+    // load a base64 encoded 32 bytes key
+    pub fn load(key: &str) -> Result<Self, AppError> {
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(|e| AppError::CryptoError(format!("invalid key: {}", e)))?;
+        if key.len() != 32 {
+            return Err(AppError::CryptoError(
+                "key must be 32 bytes long".to_string(),
+            ));
+        }
+        Ok(Self(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+    }
+
+    // `auth.totp_key`, or a key derived from the signing key for configs from
+    // before it was added
+    pub fn from_config(config: &AuthConfig) -> Result<Self, AppError> {
+        match &config.totp_key {
+            Some(key) => Self::load(key),
+            None => {
+                warn!("auth.totp_key is not set, using a key derived from auth.sk");
+                let key = Sha256::new()
+                    .chain_update(b"chat cipher key:")
+                    .chain_update(config.sk.trim().as_bytes())
+                    .finalize();
+                Ok(Self(Aes256Gcm::new(&key)))
+            }
+        }
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, AppError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext)
+            .map_err(|e| AppError::CryptoError(e.to_string()))?;
+
+        let mut ret = nonce.to_vec();
+        ret.extend(ciphertext);
+        Ok(ret)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, AppError> {
+        if data.len() < NONCE_LEN {
+            return Err(AppError::CryptoError("ciphertext too short".to_string()));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|e| AppError::CryptoError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt_should_work() -> anyhow::Result<()> {
+        let key = CipherKey::load(&STANDARD.encode([7u8; 32]))?;
+        let data = key.encrypt(b"secret")?;
+        assert_ne!(&data[NONCE_LEN..], b"secret");
+        assert_eq!(key.decrypt(&data)?, b"secret");
+
+        let other = CipherKey::load(&STANDARD.encode([8u8; 32]))?;
+        assert!(other.decrypt(&data).is_err());
+        assert!(CipherKey::load("c2hvcnQ=").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn key_should_be_derived_without_totp_key() -> anyhow::Result<()> {
+        let mut config: AuthConfig = serde_json::from_str(r#"{"sk": "sk", "pk": "pk"}"#)?;
+        let data = CipherKey::from_config(&config)?.encrypt(b"secret")?;
+        assert_eq!(CipherKey::from_config(&config)?.decrypt(&data)?, b"secret");
+
+        config.sk = "other".to_string();
+        assert!(CipherKey::from_config(&config)?.decrypt(&data).is_err());
+        Ok(())
+    }
+}
@@ -1,13 +1,45 @@
 use crate::{AppError, User};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chat_core::{JWT_AUDIENCE, JWT_ISSUER};
 use jwt_simple::prelude::*;
 
 const JWT_DURATION: u64 = 60 * 60 * 24 * 7; // 1 week
-const JWT_ISSUER: &str = "chat_server";
-const JWT_AUDIENCE: &str = "chat_web";
+const CHALLENGE_DURATION: u64 = 60 * 5; // 5 minutes
+const CHALLENGE_AUDIENCE: &str = "chat_2fa";
+const OIDC_STATE_DURATION: u64 = 60 * 10; // 10 minutes, as long as the login
+const OIDC_STATE_AUDIENCE: &str = "chat_oidc";
+
+// a password sign-in waiting for the second factor, `enroll` is set when the user
+// has to set up 2fa first. wrong codes are counted by `id`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChallengeClaims {
+    pub id: String,
+    pub user: User,
+    pub enroll: bool,
+}
+
+impl ChallengeClaims {
+    pub fn new(user: User, enroll: bool) -> Self {
+        let mut id = [0u8; 16];
+        OsRng.fill_bytes(&mut id);
+        Self {
+            id: id.iter().map(|b| format!("{:02x}", b)).collect(),
+            user,
+            enroll,
+        }
+    }
+}
+
+// the state of an oidc login, kept in a cookie of the browser which started it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct OidcStateClaims {
+    state: String,
+}
 
 pub struct EncodingKey(Ed25519KeyPair);
 
-pub struct DecodingKey(Ed25519PublicKey);
+// the verifier shared with notify_server, which knows the claims of chat_server
+pub struct DecodingKey(chat_core::DecodingKey);
 
 impl EncodingKey {
     pub fn load(pem: &str) -> Result<Self, AppError> {
@@ -19,23 +51,50 @@ impl EncodingKey {
         let claims = claims.with_issuer(JWT_ISSUER).with_audience(JWT_AUDIENCE);
         Ok(self.0.sign(claims)?)
     }
+
+    pub fn sign_challenge(&self, challenge: ChallengeClaims) -> Result<String, AppError> {
+        let claims = Claims::with_custom_claims(challenge, Duration::from_secs(CHALLENGE_DURATION));
+        let claims = claims
+            .with_issuer(JWT_ISSUER)
+            .with_audience(CHALLENGE_AUDIENCE);
+        Ok(self.0.sign(claims)?)
+    }
+
+    pub fn sign_oidc_state(&self, state: &str) -> Result<String, AppError> {
+        let claims = OidcStateClaims {
+            state: state.to_string(),
+        };
+        let claims = Claims::with_custom_claims(claims, Duration::from_secs(OIDC_STATE_DURATION));
+        let claims = claims
+            .with_issuer(JWT_ISSUER)
+            .with_audience(OIDC_STATE_AUDIENCE);
+        Ok(self.0.sign(claims)?)
+    }
 }
 
 impl DecodingKey {
     pub fn load(pem: &str) -> Result<Self, AppError> {
-        Ok(Self(Ed25519PublicKey::from_pem(pem)?))
+        Ok(Self(chat_core::DecodingKey::load(pem)?))
     }
 
-    #[allow(unused)]
+    // requests check the token is not revoked as well, with `verify_issued`
+    #[cfg(test)]
     pub fn verify(&self, token: &str) -> Result<User, AppError> {
-        let options = VerificationOptions {
-            allowed_issuers: Some(HashSet::from_strings(&[JWT_ISSUER])),
-            allowed_audiences: Some(HashSet::from_strings(&[JWT_AUDIENCE])),
-            ..Default::default()
-        };
+        Ok(self.0.verify(token)?)
+    }
 
-        let claims = self.0.verify_token::<User>(token, Some(options))?;
-        Ok(claims.custom)
+    // the user of an access token and when it was issued, see `token_revoked`
+    pub fn verify_issued(&self, token: &str) -> Result<(User, u64), AppError> {
+        Ok(self.0.verify_issued(token)?)
+    }
+
+    pub fn verify_challenge(&self, token: &str) -> Result<ChallengeClaims, AppError> {
+        Ok(self.0.verify_for(token, CHALLENGE_AUDIENCE)?)
+    }
+
+    pub fn verify_oidc_state(&self, token: &str) -> Result<String, AppError> {
+        let claims: OidcStateClaims = self.0.verify_for(token, OIDC_STATE_AUDIENCE)?;
+        Ok(claims.state)
     }
 }
 
@@ -58,6 +117,19 @@ mod tests {
 
         assert_eq!(user, user2);
 
+        // challenge tokens can't be used as access tokens and the other way around
+        let challenge = ChallengeClaims::new(user.clone(), false);
+        let token = ek.sign_challenge(challenge.clone())?;
+        assert!(dk.verify(&token).is_err());
+        assert_eq!(dk.verify_challenge(&token)?, challenge);
+        let token = ek.sign(user)?;
+        assert!(dk.verify_challenge(&token).is_err());
+        assert!(dk.verify_oidc_state(&token).is_err());
+
+        let token = ek.sign_oidc_state("abc")?;
+        assert_eq!(dk.verify_oidc_state(&token)?, "abc");
+        assert!(dk.verify(&token).is_err());
+
         Ok(())
     }
 }
@@ -0,0 +1,279 @@
+use std::time::Duration;
+
+use ldap3::{dn_escape, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+
+use crate::{config::LdapConfig, AppError};
+
+const LDAP_TIMEOUT: Duration = Duration::from_secs(5);
+// ldap result code for a wrong dn or password
+const INVALID_CREDENTIALS: u32 = 49;
+
+// the directory entry of an authenticated user
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdapEntry {
+    pub dn: String,
+    pub fullname: String,
+    pub email: String,
+}
+
+pub struct LdapClient {
+    config: LdapConfig,
+}
+
+impl LdapClient {
+    pub fn new(config: LdapConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &LdapConfig {
+        &self.config
+    }
+
+    // bind as the user and read the mapped attributes of its entry
+    pub async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<LdapEntry>, AppError> {
+        // a bind without password is an anonymous bind, which always succeeds
+        let username = username.split('@').next().unwrap_or_default();
+        if username.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(LDAP_TIMEOUT)
+            .set_starttls(self.config.starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.config.url).await?;
+        ldap3::drive!(conn);
+
+        let dn = self
+            .config
+            .bind_dn
+            .replace("{username}", &dn_escape(username));
+        let ret = ldap
+            .with_timeout(LDAP_TIMEOUT)
+            .simple_bind(&dn, password)
+            .await?;
+        if ret.rc == INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+        ret.success()?;
+
+        let attrs = [&self.config.fullname_attr, &self.config.email_attr];
+        let (entries, _) = ldap
+            .with_timeout(LDAP_TIMEOUT)
+            .search(&dn, Scope::Base, "(objectClass=*)", attrs.to_vec())
+            .await?
+            .success()?;
+        let _ = ldap.unbind().await;
+
+        let entry = entries
+            .into_iter()
+            .next()
+            .map(SearchEntry::construct)
+            .ok_or_else(|| AppError::NotFound(format!("ldap entry {}", dn)))?;
+        let attr = |name: &str| entry.attrs.get(name).and_then(|v| v.first()).cloned();
+        let email = attr(&self.config.email_attr).ok_or_else(|| {
+            AppError::InvalidInput(format!(
+                "ldap entry {} has no {}",
+                dn, self.config.email_attr
+            ))
+        })?;
+        let fullname = attr(&self.config.fullname_attr).unwrap_or_else(|| email.clone());
+
+        Ok(Some(LdapEntry {
+            dn: entry.dn,
+            fullname,
+            email,
+        }))
+    }
+}
+
+// a minimal in-process ldap server, which only supports simple bind and base searches
+#[cfg(test)]
+pub(crate) mod test_util {
+    use std::collections::HashMap;
+
+    use ldap3::asn1::parse_tag;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    // dn to password and attributes
+    pub type Directory = HashMap<String, (String, Vec<(String, String)>)>;
+
+    // a single user alice, with password `secret`
+    pub fn test_directory() -> Directory {
+        let attrs = vec![
+            ("cn".to_string(), "Alice Chen".to_string()),
+            ("mail".to_string(), "alice@acme.org".to_string()),
+        ];
+        Directory::from([(
+            "uid=alice,ou=people,dc=acme,dc=org".to_string(),
+            ("secret".to_string(), attrs),
+        )])
+    }
+
+    pub async fn start_ldap_server(directory: Directory) -> anyhow::Result<u16> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let directory = directory.clone();
+                tokio::spawn(async move { serve(stream, &directory).await });
+            }
+        });
+
+        Ok(port)
+    }
+
+    async fn serve(mut stream: TcpStream, directory: &Directory) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        let mut bound: Option<String> = None;
+        loop {
+            let mut chunk = [0u8; 4096];
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            buf.extend_from_slice(&chunk[..n]);
+
+            while let Ok((rest, tag)) = parse_tag(&buf) {
+                let consumed = buf.len() - rest.len();
+                let mut msg = tag.expect_constructed().unwrap_or_default().into_iter();
+                let (Some(id), Some(op)) = (msg.next(), msg.next()) else {
+                    return Ok(());
+                };
+                let id = id.expect_primitive().unwrap_or_default();
+                let (op_id, fields) = (op.id, op.expect_constructed().unwrap_or_default());
+                let field = |i: usize| {
+                    fields
+                        .get(i)
+                        .cloned()
+                        .and_then(|t| t.expect_primitive())
+                        .map(|v| String::from_utf8_lossy(&v).to_string())
+                        .unwrap_or_default()
+                };
+
+                match op_id {
+                    // bind request
+                    0 => {
+                        let (dn, password) = (field(1), field(2));
+                        let ok = !password.is_empty()
+                            && directory.get(&dn).is_some_and(|(p, _)| *p == password);
+                        bound = ok.then_some(dn);
+                        let rc = if ok { 0 } else { 49 };
+                        stream.write_all(&message(&id, result(1, rc))).await?;
+                    }
+                    // search request, only the bound user can read its own entry
+                    3 => {
+                        let base = field(0);
+                        match directory
+                            .get(&base)
+                            .filter(|_| bound.as_ref() == Some(&base))
+                        {
+                            Some((_, attrs)) => {
+                                let attrs = attrs
+                                    .iter()
+                                    .map(|(k, v)| {
+                                        constructed(
+                                            0x30,
+                                            vec![
+                                                octets(0x04, k),
+                                                constructed(0x31, vec![octets(0x04, v)]),
+                                            ],
+                                        )
+                                    })
+                                    .collect();
+                                let entry = constructed(
+                                    0x64,
+                                    vec![octets(0x04, &base), constructed(0x30, attrs)],
+                                );
+                                stream.write_all(&message(&id, entry)).await?;
+                                stream.write_all(&message(&id, result(5, 0))).await?;
+                            }
+                            None => stream.write_all(&message(&id, result(5, 32))).await?,
+                        }
+                    }
+                    // unbind request
+                    _ => return Ok(()),
+                }
+                buf.drain(..consumed);
+            }
+        }
+    }
+
+    fn message(id: &[u8], op: Vec<u8>) -> Vec<u8> {
+        let mut payload = encode(0x02, id);
+        payload.extend(op);
+        encode(0x30, &payload)
+    }
+
+    // ldap result of the given application tag, with empty matched dn and message
+    fn result(app_id: u8, rc: u8) -> Vec<u8> {
+        constructed(
+            0x60 | app_id,
+            vec![encode(0x0a, &[rc]), octets(0x04, ""), octets(0x04, "")],
+        )
+    }
+
+    fn constructed(tag: u8, children: Vec<Vec<u8>>) -> Vec<u8> {
+        encode(tag, &children.concat())
+    }
+
+    fn octets(tag: u8, value: &str) -> Vec<u8> {
+        encode(tag, value.as_bytes())
+    }
+
+    fn encode(tag: u8, payload: &[u8]) -> Vec<u8> {
+        let len = payload.len();
+        let mut ret = vec![tag];
+        if len < 0x80 {
+            ret.push(len as u8);
+        } else {
+            ret.extend([0x82, (len >> 8) as u8, len as u8]);
+        }
+        ret.extend_from_slice(payload);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_util::*;
+    use super::*;
+
+    #[tokio::test]
+    async fn ldap_authenticate_should_work() -> anyhow::Result<()> {
+        let port = start_ldap_server(test_directory()).await?;
+        let client = LdapClient::new(LdapConfig {
+            url: format!("ldap://127.0.0.1:{}", port),
+            starttls: false,
+            bind_dn: "uid={username},ou=people,dc=acme,dc=org".to_string(),
+            fullname_attr: "cn".to_string(),
+            email_attr: "mail".to_string(),
+            workspace: "acme".to_string(),
+        });
+
+        let entry = client.authenticate("alice@acme.org", "secret").await?;
+        assert_eq!(
+            entry,
+            Some(LdapEntry {
+                dn: "uid=alice,ou=people,dc=acme,dc=org".to_string(),
+                fullname: "Alice Chen".to_string(),
+                email: "alice@acme.org".to_string(),
+            })
+        );
+
+        assert_eq!(client.authenticate("alice", "wrong").await?, None);
+        assert_eq!(client.authenticate("alice", "").await?, None);
+        // the username is escaped, so it can't point the bind to another entry
+        let ret = client.authenticate("alice,ou=people", "secret").await?;
+        assert_eq!(ret, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use minijinja::Environment;
+use serde::Serialize;
+
+use crate::{
+    config::{EmailConfig, SmtpTls},
+    AppError,
+};
+
+// every email is rendered from a `<name>.html` and a `<name>.txt` template
+const TEMPLATES: &[(&str, &str)] = &[
+    ("digest.html", include_str!("../../templates/digest.html")),
+    ("digest.txt", include_str!("../../templates/digest.txt")),
+    (
+        "verify_email.html",
+        include_str!("../../templates/verify_email.html"),
+    ),
+    (
+        "verify_email.txt",
+        include_str!("../../templates/verify_email.txt"),
+    ),
+    (
+        "reset_password.html",
+        include_str!("../../templates/reset_password.html"),
+    ),
+    (
+        "reset_password.txt",
+        include_str!("../../templates/reset_password.txt"),
+    ),
+];
+
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    templates: Environment<'static>,
+}
+
+impl Mailer {
+    pub fn try_new(config: &EmailConfig) -> Result<Self, AppError> {
+        let smtp = &config.smtp;
+        let mut builder = match smtp.tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.host),
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host)?,
+        }
+        .port(smtp.port);
+        if let (Some(username), Some(password)) = (&smtp.username, &smtp.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let from = config.from.parse().map_err(|e| {
+            AppError::InvalidInput(format!("invalid sender {}: {}", config.from, e))
+        })?;
+
+        let mut templates = Environment::new();
+        templates.set_trim_blocks(true);
+        templates.set_lstrip_blocks(true);
+        for (name, source) in TEMPLATES {
+            templates.add_template(name, source)?;
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+            templates,
+        })
+    }
+
+    // render the html and text version of a template and send them as one email
+    pub async fn send(
+        &self,
+        to: Mailbox,
+        subject: &str,
+        template: &str,
+        ctx: impl Serialize,
+    ) -> Result<(), AppError> {
+        let html = self
+            .templates
+            .get_template(&format!("{}.html", template))?
+            .render(&ctx)?;
+        let text = self
+            .templates
+            .get_template(&format!("{}.txt", template))?
+            .render(&ctx)?;
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .multipart(MultiPart::alternative_plain_html(text, html))?;
+        self.transport.send(message).await?;
+
+        Ok(())
+    }
+}
@@ -1,3 +1,19 @@
+mod crypto;
 mod jwt;
+mod ldap;
+mod mailer;
+mod oidc;
+mod outbound;
+mod rate_limit;
+mod signature;
 
-pub use jwt::{DecodingKey, EncodingKey};
+pub use crypto::CipherKey;
+pub use jwt::{ChallengeClaims, DecodingKey, EncodingKey};
+#[cfg(test)]
+pub(crate) use ldap::test_util::{start_ldap_server, test_directory};
+pub use ldap::LdapClient;
+pub use mailer::Mailer;
+pub use oidc::{AuthRequest, OidcClient};
+pub use outbound::{check_url, describe_error, outbound_client};
+pub use rate_limit::{RateDecision, RateLimiter};
+pub use signature::{sign_payload, SIGNATURE_HEADER, TIMESTAMP_HEADER};
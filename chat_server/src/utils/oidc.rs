@@ -0,0 +1,235 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jwt_simple::prelude::*;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+
+use crate::{config::OidcConfig, models::ExternalIdentity, AppError};
+
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IdTokenClaims {
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: Option<bool>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+// where to send the user, and what to keep until the provider redirects back
+#[derive(Debug, Clone)]
+pub struct AuthRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+// authorization code flow with PKCE against an OpenID Connect provider
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+            metadata: OnceCell::new(),
+        }
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    pub async fn auth_request(&self) -> Result<AuthRequest, AppError> {
+        let metadata = self.metadata().await?;
+        let state = random_string();
+        let nonce = random_string();
+        let code_verifier = random_string();
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.config.client_id),
+                ("redirect_uri", &self.config.redirect_uri),
+                ("scope", &self.config.scopes.join(" ")),
+                ("state", &state),
+                ("nonce", &nonce),
+                ("code_challenge", &code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| AppError::OidcError(format!("invalid authorization endpoint: {}", e)))?;
+
+        Ok(AuthRequest {
+            url: url.to_string(),
+            state,
+            nonce,
+            code_verifier,
+        })
+    }
+
+    // exchange the authorization code for an id token and validate its claims
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, AppError> {
+        let metadata = self.metadata().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_uri),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret));
+        }
+
+        let ret: TokenResponse = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| AppError::OidcError(format!("token request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| AppError::OidcError(format!("invalid token response: {}", e)))?;
+
+        self.verify_id_token(&ret.id_token, nonce).await
+    }
+
+    async fn verify_id_token(
+        &self,
+        token: &str,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, AppError> {
+        let metadata = self.metadata().await?;
+        let header = Token::decode_metadata(token)?;
+        if header.algorithm() != "RS256" {
+            return Err(AppError::OidcError(format!(
+                "unsupported id token algorithm: {}",
+                header.algorithm()
+            )));
+        }
+
+        let jwks: Jwks = self.get_json(&metadata.jwks_uri).await?;
+        let jwk = jwks
+            .keys
+            .iter()
+            .filter(|k| k.kty == "RSA")
+            .find(|k| header.key_id().is_none() || k.kid.as_deref() == header.key_id())
+            .ok_or_else(|| AppError::OidcError("no matching key in jwks".to_string()))?;
+        let (Some(n), Some(e)) = (&jwk.n, &jwk.e) else {
+            return Err(AppError::OidcError("invalid rsa key in jwks".to_string()));
+        };
+        let decode = |v: &str| {
+            URL_SAFE_NO_PAD
+                .decode(v)
+                .map_err(|e| AppError::OidcError(format!("invalid rsa key in jwks: {}", e)))
+        };
+        let key = RS256PublicKey::from_components(&decode(n)?, &decode(e)?)?;
+
+        let options = VerificationOptions {
+            allowed_issuers: Some(HashSet::from_strings(&[&metadata.issuer])),
+            allowed_audiences: Some(HashSet::from_strings(&[&self.config.client_id])),
+            required_nonce: Some(nonce.to_string()),
+            ..Default::default()
+        };
+        let claims = key.verify_token::<IdTokenClaims>(token, Some(options))?;
+
+        let subject = claims
+            .subject
+            .ok_or_else(|| AppError::OidcError("id token has no subject".to_string()))?;
+        let email = claims
+            .custom
+            .email
+            .ok_or_else(|| AppError::OidcError("id token has no email".to_string()))?;
+        Ok(ExternalIdentity {
+            issuer: metadata.issuer.clone(),
+            subject,
+            email,
+            email_verified: claims.custom.email_verified.unwrap_or(false),
+            name: claims.custom.name,
+        })
+    }
+
+    async fn metadata(&self) -> Result<&ProviderMetadata, AppError> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                let metadata: ProviderMetadata = self.get_json(&url).await?;
+                if metadata.issuer.trim_end_matches('/') != self.config.issuer.trim_end_matches('/')
+                {
+                    return Err(AppError::OidcError(format!(
+                        "issuer mismatch: {}",
+                        metadata.issuer
+                    )));
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, AppError> {
+        self.http
+            .get(url)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| AppError::OidcError(format!("request to {} failed: {}", url, e)))?
+            .json()
+            .await
+            .map_err(|e| AppError::OidcError(format!("invalid response from {}: {}", url, e)))
+    }
+}
+
+fn random_string() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
@@ -0,0 +1,166 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::Policy,
+    Client, Url,
+};
+
+use crate::config::OutboundConfig;
+
+const MAX_URL_LEN: usize = 2048;
+
+// requests to urls users configured, i.e. webhooks and slash commands. they must
+// not reach the server's own network: the host is resolved on every connection
+// and private addresses are refused, so a dns change can't get around the check
+// done when the url is saved. redirects aren't followed for the same reason
+pub fn outbound_client(config: &OutboundConfig) -> Client {
+    let builder = Client::builder().redirect(Policy::none());
+    let builder = if config.allow_insecure {
+        builder
+    } else {
+        builder
+            .https_only(true)
+            .dns_resolver(Arc::new(PublicResolver))
+    };
+    builder
+        .build()
+        .expect("the outbound client config is valid")
+}
+
+// the url a user wants the server to call, or the reason it can't be called
+pub fn check_url(url: &str, config: &OutboundConfig) -> Result<Url, String> {
+    if url.len() > MAX_URL_LEN {
+        return Err(format!(
+            "url can't be longer than {} characters",
+            MAX_URL_LEN
+        ));
+    }
+    let url = Url::parse(url).map_err(|e| format!("invalid url: {}", e))?;
+    if config.allow_insecure {
+        return match url.scheme() {
+            "http" | "https" => Ok(url),
+            _ => Err("url must be a http or https url".to_string()),
+        };
+    }
+
+    if url.scheme() != "https" {
+        return Err("url must be a https url".to_string());
+    }
+    // ip literals don't go through the resolver
+    let host = url.host_str().ok_or("url must have a host")?;
+    let ip = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => Some(ip),
+        Err(_) if host.eq_ignore_ascii_case("localhost") => Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        Err(_) => None,
+    };
+    if ip.is_some_and(|ip| !is_public(ip)) {
+        return Err("url must not point to a private address".to_string());
+    }
+    Ok(url)
+}
+
+// what went wrong with an outbound request, without the details of the network
+// behind the url, which are only logged
+pub fn describe_error(e: &reqwest::Error) -> &'static str {
+    if e.is_timeout() {
+        "request timed out"
+    } else if e.is_connect() {
+        "connection failed"
+    } else if e.is_decode() {
+        "invalid response body"
+    } else {
+        "request failed"
+    }
+}
+
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // carrier-grade nat, 100.64.0.0/10
+        || (a == 100 && (b & 0xc0) == 64)
+        // "this network", 0.0.0.0/8
+        || a == 0)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // link local, fe80::/10
+        || (first & 0xffc0) == 0xfe80)
+}
+
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            let public: Vec<SocketAddr> = addrs.into_iter().filter(|a| is_public(a.ip())).collect();
+            if public.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(public.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_url_should_reject_private_addresses() {
+        let config = OutboundConfig::default();
+        assert!(check_url("https://hooks.acme.org/chat", &config).is_ok());
+        assert!(check_url("https://1.1.1.1/hook", &config).is_ok());
+        for url in [
+            "http://hooks.acme.org/chat",
+            "https://localhost:8080/hook",
+            "https://127.0.0.1/hook",
+            "https://10.0.0.1/hook",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]/hook",
+            "https://[::ffff:192.168.0.1]/hook",
+            "https://[fd00::1]/hook",
+            "ftp://hooks.acme.org",
+        ] {
+            assert!(check_url(url, &config).is_err(), "{}", url);
+        }
+
+        let config = OutboundConfig {
+            allow_insecure: true,
+        };
+        assert!(check_url("http://127.0.0.1:8080/hook", &config).is_ok());
+    }
+
+    #[tokio::test]
+    async fn resolver_should_refuse_private_hosts() {
+        let ret = PublicResolver.resolve("localhost".parse().unwrap()).await;
+        assert!(ret.is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use sqlx::PgPool;
+
+use crate::{
+    config::{RateLimit, RateLimitBackend, RateLimitConfig},
+    AppError,
+};
+
+// stale buckets are dropped every this many requests
+const PRUNE_EVERY: u64 = 1000;
+// a bucket left alone this long is full again with any sane limit
+const IDLE_SECS: u64 = 3600;
+// buckets kept by the memory backend, a client spraying addresses can't grow it
+// past that
+const MAX_BUCKETS: usize = 100_000;
+
+// token buckets, one per key. a bucket holds up to `burst` tokens, refills at
+// `per_minute` and every request takes one
+pub struct RateLimiter {
+    backend: Backend,
+    requests: AtomicU64,
+}
+
+enum Backend {
+    Memory(Mutex<Buckets>),
+    Postgres(PgPool),
+}
+
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    max: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    // a full bucket is the same as no bucket, it can be dropped from then on
+    full_at: Instant,
+}
+
+// what a request gets from the bucket, for the X-RateLimit-* headers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // seconds until a token is available, 0 when allowed
+    pub retry_after: u64,
+    // seconds until the bucket is full
+    pub reset: u64,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig, pool: PgPool) -> Self {
+        let backend = match config.backend {
+            RateLimitBackend::Memory => Backend::Memory(Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                max: MAX_BUCKETS,
+            })),
+            RateLimitBackend::Postgres => Backend::Postgres(pool),
+        };
+        Self {
+            backend,
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn take(&self, key: &str, limit: &RateLimit) -> Result<RateDecision, AppError> {
+        let prune = self
+            .requests
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(PRUNE_EVERY);
+        match &self.backend {
+            Backend::Memory(buckets) => {
+                let mut buckets = buckets.lock().unwrap_or_else(|e| e.into_inner());
+                let Buckets { buckets, max } = &mut *buckets;
+                let now = Instant::now();
+                let full = !buckets.contains_key(key) && buckets.len() >= *max;
+                if prune || full {
+                    buckets.retain(|_, b| b.full_at > now);
+                }
+                if buckets.len() >= *max && !buckets.contains_key(key) {
+                    return Err(AppError::RateLimiterFull);
+                }
+
+                let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+                    tokens: limit.burst as f64,
+                    updated_at: now,
+                    full_at: now,
+                });
+                let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+                let (tokens, decision) = limit.take(limit.refill(bucket.tokens, elapsed));
+                *bucket = Bucket {
+                    tokens,
+                    updated_at: now,
+                    full_at: now + Duration::from_secs_f64(limit.refill_secs(tokens)),
+                };
+                Ok(decision)
+            }
+            Backend::Postgres(pool) => {
+                if prune {
+                    sqlx::query(
+                        "DELETE FROM rate_limits WHERE updated_at < NOW() - make_interval(secs => $1)",
+                    )
+                    .bind(IDLE_SECS as f64)
+                    .execute(pool)
+                    .await?;
+                }
+
+                // refill, then take a token if there is one. each statement is
+                // atomic, so concurrent requests never take the same token
+                let (tokens,): (f64,) = sqlx::query_as(
+                    r#"
+                    INSERT INTO rate_limits AS r (key, tokens)
+                    VALUES ($1, $2)
+                    ON CONFLICT (key) DO UPDATE
+                    SET tokens = LEAST($2, r.tokens + EXTRACT(EPOCH FROM NOW() - r.updated_at) * $3),
+                        updated_at = NOW()
+                    RETURNING tokens
+                    "#,
+                )
+                .bind(key)
+                .bind(limit.burst as f64)
+                .bind(limit.per_sec())
+                .fetch_one(pool)
+                .await?;
+
+                let taken: Option<(f64,)> = sqlx::query_as(
+                    r#"
+                    UPDATE rate_limits
+                    SET tokens = tokens - 1
+                    WHERE key = $1 AND tokens >= 1
+                    RETURNING tokens
+                    "#,
+                )
+                .bind(key)
+                .fetch_optional(pool)
+                .await?;
+
+                let decision = match taken {
+                    Some((tokens,)) => limit.decision(true, tokens),
+                    None => limit.decision(false, tokens),
+                };
+                Ok(decision)
+            }
+        }
+    }
+}
+
+impl RateLimit {
+    fn per_sec(&self) -> f64 {
+        self.per_minute as f64 / 60.0
+    }
+
+    fn refill(&self, tokens: f64, elapsed_secs: f64) -> f64 {
+        (tokens + elapsed_secs * self.per_sec()).min(self.burst as f64)
+    }
+
+    // seconds until a bucket holding `tokens` is full
+    fn refill_secs(&self, tokens: f64) -> f64 {
+        ((self.burst as f64 - tokens) / self.per_sec()).max(0.0)
+    }
+
+    // the tokens left and the decision for a bucket holding `tokens`
+    fn take(&self, tokens: f64) -> (f64, RateDecision) {
+        if tokens >= 1.0 {
+            (tokens - 1.0, self.decision(true, tokens - 1.0))
+        } else {
+            (tokens, self.decision(false, tokens))
+        }
+    }
+
+    fn decision(&self, allowed: bool, tokens: f64) -> RateDecision {
+        let retry_after = if allowed {
+            0
+        } else {
+            ((1.0 - tokens) / self.per_sec()).ceil() as u64
+        };
+        RateDecision {
+            allowed,
+            limit: self.burst,
+            remaining: tokens.max(0.0) as u32,
+            retry_after,
+            reset: self.refill_secs(tokens).ceil() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppConfig;
+
+    const LIMIT: RateLimit = RateLimit {
+        burst: 2,
+        per_minute: 6,
+    };
+
+    #[test]
+    fn bucket_should_allow_burst_then_refill() {
+        let (tokens, ret) = LIMIT.take(LIMIT.burst as f64);
+        assert!(ret.allowed);
+        assert_eq!(ret.remaining, 1);
+        let (tokens, ret) = LIMIT.take(tokens);
+        assert!(ret.allowed);
+        assert_eq!(ret.remaining, 0);
+        assert_eq!(ret.reset, 20);
+
+        let (tokens, ret) = LIMIT.take(tokens);
+        assert!(!ret.allowed);
+        // a token every 10 seconds
+        assert_eq!(ret.retry_after, 10);
+
+        let (_, ret) = LIMIT.take(LIMIT.refill(tokens, 10.0));
+        assert!(ret.allowed);
+        assert_eq!(LIMIT.refill(0.0, 3600.0), 2.0);
+    }
+
+    #[tokio::test]
+    async fn backends_should_limit_by_key() -> anyhow::Result<()> {
+        let (_tdb, state) = crate::AppState::try_new_for_test(AppConfig::load()?).await?;
+        for backend in [RateLimitBackend::Memory, RateLimitBackend::Postgres] {
+            let config = RateLimitConfig {
+                backend,
+                ..Default::default()
+            };
+            let limiter = RateLimiter::new(&config, state.pool.clone());
+
+            assert!(limiter.take("api:1", &LIMIT).await?.allowed);
+            assert!(limiter.take("api:1", &LIMIT).await?.allowed);
+            let ret = limiter.take("api:1", &LIMIT).await?;
+            assert!(!ret.allowed, "{:?}", backend);
+            assert!(ret.retry_after > 0 && ret.retry_after <= 10);
+
+            // other clients have their own bucket
+            assert!(limiter.take("api:2", &LIMIT).await?.allowed);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_backend_should_be_bounded() -> anyhow::Result<()> {
+        let (_tdb, state) = crate::AppState::try_new_for_test(AppConfig::load()?).await?;
+        let limiter = RateLimiter::new(&RateLimitConfig::default(), state.pool.clone());
+        if let Backend::Memory(buckets) = &limiter.backend {
+            buckets.lock().unwrap().max = 2;
+        }
+
+        assert!(limiter.take("public:1.1.1.1", &LIMIT).await?.allowed);
+        assert!(limiter.take("public:2.2.2.2", &LIMIT).await?.allowed);
+        let ret = limiter.take("public:3.3.3.3", &LIMIT).await;
+        assert!(matches!(ret, Err(AppError::RateLimiterFull)));
+        // known clients still get their bucket
+        assert!(limiter.take("public:1.1.1.1", &LIMIT).await?.allowed);
+
+        // full buckets are dropped to make room
+        if let Backend::Memory(buckets) = &limiter.backend {
+            let mut buckets = buckets.lock().unwrap();
+            let bucket = buckets.buckets.get_mut("public:2.2.2.2").unwrap();
+            bucket.full_at = Instant::now();
+        }
+        assert!(limiter.take("public:3.3.3.3", &LIMIT).await?.allowed);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+// headers of the signed requests chat_server sends to integrations
+pub const SIGNATURE_HEADER: &str = "x-chat-signature";
+pub const TIMESTAMP_HEADER: &str = "x-chat-timestamp";
+
+// receivers recompute the HMAC-SHA256 of `{timestamp}.{body}` with their secret, and
+// reject old timestamps to prevent replays
+pub fn sign_payload(secret: &[u8], timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
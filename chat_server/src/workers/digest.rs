@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use lettre::message::Mailbox;
+use tracing::{info, warn};
+
+use crate::{models::Digest, AppError, AppState};
+
+// periodically email users a digest of the direct messages and mentions they missed
+pub(crate) fn spawn_digest_worker(state: AppState) {
+    let Some(config) = &state.config.email else {
+        info!("Email is not configured, digest worker disabled");
+        return;
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(config.digest_interval));
+
+    tokio::spawn(async move {
+        loop {
+            interval.tick().await;
+            match send_digests(&state).await {
+                Ok(0) => {}
+                Ok(n) => info!("Sent {} email digests", n),
+                Err(e) => warn!("Failed to send email digests: {}", e),
+            }
+        }
+    });
+}
+
+pub(crate) async fn send_digests(state: &AppState) -> Result<usize, AppError> {
+    let (Some(config), Some(mailer)) = (&state.config.email, &state.mailer) else {
+        return Ok(0);
+    };
+
+    let digests = Digest::load_pending(config.digest_delay, &state.pool).await?;
+    let mut sent = 0;
+    for digest in digests {
+        // another server is sending it
+        if !digest.claim(&state.pool).await? {
+            continue;
+        }
+        let to = match digest.email.parse() {
+            Ok(email) => Mailbox::new(Some(digest.fullname.clone()), email),
+            Err(e) => {
+                // the messages stay claimed, otherwise they are retried on every run
+                warn!(
+                    "Invalid email {} of user {}: {}",
+                    digest.email, digest.user_id, e
+                );
+                continue;
+            }
+        };
+
+        let subject = match digest.total {
+            1 => "You have 1 unread message".to_string(),
+            n => format!("You have {} unread messages", n),
+        };
+        // a failed digest is retried on the next run
+        if let Err(e) = mailer.send(to, &subject, "digest", &digest).await {
+            warn!("Failed to send digest to user {}: {}", digest.user_id, e);
+            digest.release(&state.pool).await?;
+            continue;
+        }
+        sent += 1;
+    }
+
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use super::*;
+    use crate::{
+        config::{EmailConfig, SmtpConfig, SmtpTls},
+        models::{Chat, ChatType, CreateChat, CreateMessage, CreateUser, Message},
+        AppConfig, User,
+    };
+
+    #[tokio::test]
+    async fn send_digests_should_work() -> Result<()> {
+        let (port, mut rx) = start_smtp_sink().await?;
+
+        let mut config = AppConfig::load()?;
+        config.email = Some(EmailConfig {
+            smtp: SmtpConfig {
+                host: "127.0.0.1".to_string(),
+                port,
+                username: None,
+                password: None,
+                tls: SmtpTls::None,
+            },
+            from: "Chat <noreply@chat.local>".to_string(),
+            base_url: "http://localhost:6688".to_string(),
+            digest_delay: 0,
+            digest_interval: 60,
+        });
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+
+        let input = CreateUser::new("default", "Alice", "alice@acme.org", "password");
+        let alice = User::create(&input, &state.pool).await?;
+        let input = CreateUser::new("default", "Bob", "bob@acme.org", "password");
+        let bob = User::create(&input, &state.pool).await?;
+        let input = CreateChat::new("", ChatType::Single, &[bob.id]);
+        let dm = Chat::create(&input, alice.ws_id as _, alice.id as _, &state.pool).await?;
+        let input = CreateMessage::new("are you <there>?", &[]);
+        Message::create(&input, dm.id as _, alice.id as _, &state.pool).await?;
+
+        assert_eq!(send_digests(&state).await?, 1);
+        // undo the soft line breaks of the quoted-printable html part
+        let email = rx.recv().await.unwrap().replace("=\n", "");
+        assert!(email.contains("To: Bob <bob@acme.org>"));
+        assert!(email.contains("Subject: You have 1 unread message"));
+        assert!(email.contains("Alice (direct message): are you <there>?"));
+        // content is escaped in the html part
+        assert!(email.contains("are you &lt;there&gt;?"));
+
+        // the message is not sent again
+        assert_eq!(send_digests(&state).await?, 0);
+
+        Ok(())
+    }
+
+    // a minimal smtp server which accepts every email and hands over its raw data
+    async fn start_smtp_sink() -> Result<(u16, mpsc::UnboundedReceiver<String>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    writer.write_all(b"220 localhost ESMTP\r\n").await?;
+                    while let Some(line) = lines.next_line().await? {
+                        let cmd = line.to_ascii_uppercase();
+                        if cmd.starts_with("EHLO") || cmd.starts_with("HELO") {
+                            writer.write_all(b"250 localhost\r\n").await?;
+                        } else if cmd.starts_with("DATA") {
+                            writer.write_all(b"354 go ahead\r\n").await?;
+                            let mut data = String::new();
+                            while let Some(line) = lines.next_line().await? {
+                                if line == "." {
+                                    break;
+                                }
+                                data.push_str(&line);
+                                data.push('\n');
+                            }
+                            let _ = tx.send(data);
+                            writer.write_all(b"250 OK\r\n").await?;
+                        } else if cmd.starts_with("QUIT") {
+                            writer.write_all(b"221 bye\r\n").await?;
+                            break;
+                        } else {
+                            writer.write_all(b"250 OK\r\n").await?;
+                        }
+                    }
+                    Ok::<_, std::io::Error>(())
+                });
+            }
+        });
+
+        Ok((port, rx))
+    }
+}
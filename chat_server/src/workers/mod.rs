@@ -0,0 +1,7 @@
+mod digest;
+mod scheduler;
+mod webhook;
+
+pub(crate) use digest::*;
+pub(crate) use scheduler::*;
+pub(crate) use webhook::*;
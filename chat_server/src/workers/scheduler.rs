@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::{
+    models::{CreateMessage, Message, Reminder, ScheduledMessage},
+    AppError, AppState,
+};
+
+// poll for due scheduled messages and reminders. several servers can run the
+// scheduler, every message and reminder is sent by one of them
+pub(crate) fn spawn_scheduler(state: AppState) {
+    let mut interval = tokio::time::interval(Duration::from_millis(
+        state.config.scheduler.poll_interval_ms,
+    ));
+
+    tokio::spawn(async move {
+        loop {
+            interval.tick().await;
+            let n = send_scheduled(&state).await;
+            if n > 0 {
+                info!("Sent {} scheduled messages and reminders", n);
+            }
+        }
+    });
+}
+
+// post one batch of due messages as their senders, and send one batch of due
+// reminders, which notify_server delivers to their users. a failing batch doesn't
+// hold up the other one
+pub(crate) async fn send_scheduled(state: &AppState) -> usize {
+    let batch_size = state.config.scheduler.batch_size;
+    let reminders = Reminder::send_due(batch_size, &state.pool)
+        .await
+        .map(|v| v.len())
+        .unwrap_or_else(|e| {
+            warn!("Failed to send reminders: {}", e);
+            0
+        });
+    let messages = post_scheduled(state, batch_size).await.unwrap_or_else(|e| {
+        warn!("Failed to post scheduled messages: {}", e);
+        0
+    });
+
+    reminders + messages
+}
+
+async fn post_scheduled(state: &AppState, batch_size: u32) -> Result<usize, AppError> {
+    let scheduled = ScheduledMessage::claim_due(batch_size, &state.pool).await?;
+    for message in &scheduled {
+        let input = CreateMessage {
+            content: message.content.clone(),
+            images: message.images.clone(),
+            mentions: message.mentions.clone(),
+            ..CreateMessage::default()
+        };
+        // e.g. the sender left the chat or it was archived in the meantime
+        let ret = Message::create(
+            &input,
+            message.chat_id as _,
+            message.sender_id as _,
+            &state.pool,
+        )
+        .await
+        .map(|m| m.id)
+        .map_err(|e| e.to_string());
+        if let Err(e) = &ret {
+            warn!("Failed to post scheduled message {}: {}", message.id, e);
+        }
+        message.finish(ret, &state.pool).await?;
+    }
+
+    Ok(scheduled.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::{
+        models::{Chat, ChatType, CreateChat, CreateScheduledMessage, CreateUser, ListMessages},
+        AppConfig, User,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn send_scheduled_should_post_due_messages() -> anyhow::Result<()> {
+        let (_tdb, state) = AppState::try_new_for_test(AppConfig::load()?).await?;
+        let input = CreateUser::new("acme", "Alice", "alice@acme.org", "hunter42");
+        let user = User::create(&input, &state.pool).await?;
+        let input = CreateChat::new("general", ChatType::PublicChannel, &[]);
+        let chat = Chat::create(&input, user.ws_id as _, user.id as _, &state.pool).await?;
+        let input = CreateScheduledMessage {
+            content: "standup in 5 minutes".to_string(),
+            images: vec![],
+            mentions: vec![],
+            send_at: Utc::now() + chrono::Duration::hours(1),
+        };
+        let scheduled =
+            ScheduledMessage::create(&input, chat.id as _, user.id as _, &state.pool).await?;
+        assert_eq!(send_scheduled(&state).await, 0);
+
+        sqlx::query("UPDATE scheduled_messages SET send_at = CURRENT_TIMESTAMP WHERE id = $1")
+            .bind(scheduled.id)
+            .execute(&state.pool)
+            .await?;
+        assert_eq!(send_scheduled(&state).await, 1);
+        assert_eq!(send_scheduled(&state).await, 0);
+
+        let messages = Message::list(
+            &ListMessages::default(),
+            chat.id as _,
+            user.id as _,
+            &state.pool,
+        )
+        .await?;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "standup in 5 minutes");
+        let message_id: Option<i64> =
+            sqlx::query_scalar("SELECT message_id FROM scheduled_messages WHERE id = $1")
+                .bind(scheduled.id)
+                .fetch_one(&state.pool)
+                .await?;
+        assert_eq!(message_id, Some(messages[0].id));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use reqwest::header::CONTENT_TYPE;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::{
+    models::{DeliveryStatus, OutgoingWebhook, WebhookDelivery},
+    utils::{check_url, describe_error, sign_payload, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    AppError, AppState,
+};
+
+const EVENT_HEADER: &str = "x-chat-event";
+const DELIVERY_HEADER: &str = "x-chat-delivery";
+
+// poll the delivery queue of the outgoing webhooks. several servers can run the
+// worker, every delivery is claimed by one of them
+pub(crate) fn spawn_webhook_worker(state: AppState) {
+    let mut interval = tokio::time::interval(Duration::from_millis(
+        state.config.webhooks.poll_interval_ms,
+    ));
+
+    tokio::spawn(async move {
+        loop {
+            interval.tick().await;
+            match deliver_webhooks(&state).await {
+                Ok(0) => {}
+                Ok(n) => info!("Attempted {} webhook deliveries", n),
+                Err(e) => warn!("Failed to deliver webhooks: {}", e),
+            }
+        }
+    });
+}
+
+// attempt one batch of due deliveries concurrently
+pub(crate) async fn deliver_webhooks(state: &AppState) -> Result<usize, AppError> {
+    let config = &state.config.webhooks;
+    // long enough for the request to time out before the delivery is claimed again
+    let lease = chrono::Duration::seconds(config.timeout_secs as i64 * 2 + 30);
+    let deliveries = WebhookDelivery::claim(config.batch_size, lease, &state.pool).await?;
+    let n = deliveries.len();
+
+    let mut tasks = JoinSet::new();
+    for delivery in deliveries {
+        let state = state.clone();
+        tasks.spawn(async move {
+            if let Err(e) = deliver(&state, &delivery).await {
+                warn!("Failed to record webhook delivery {}: {}", delivery.id, e);
+            }
+        });
+    }
+    while tasks.join_next().await.is_some() {}
+
+    Ok(n)
+}
+
+async fn deliver(state: &AppState, delivery: &WebhookDelivery) -> Result<(), AppError> {
+    let config = &state.config.webhooks;
+    let backoff = chrono::Duration::seconds(config.retry_backoff_secs as i64);
+    // deliveries are removed together with their webhook
+    let Some(webhook) = OutgoingWebhook::find_by_id(delivery.webhook_id as _, &state.pool).await?
+    else {
+        return Ok(());
+    };
+
+    // the url was checked when the webhook was saved, but the config may have
+    // changed since
+    if let Err(e) = check_url(&webhook.url, &state.config.outbound) {
+        let status = delivery.fail(None, &e, 0, backoff, &state.pool).await?;
+        warn!(
+            "Webhook delivery {} to {} is {:?}: {}",
+            delivery.id, webhook.url, status, e
+        );
+        return Ok(());
+    }
+
+    let body = delivery.payload.to_string();
+    let timestamp = Utc::now().timestamp();
+    let signature = sign_payload(
+        &webhook.signing_secret(&state.ck)?,
+        timestamp,
+        body.as_bytes(),
+    );
+    let ret = state
+        .http
+        .post(&webhook.url)
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .headers(chat_core::trace_headers())
+        .header(CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, delivery.event.to_string())
+        .header(DELIVERY_HEADER, delivery.id)
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(SIGNATURE_HEADER, format!("sha256={}", signature))
+        .body(body)
+        .send()
+        .await;
+
+    // the delivery log is shown to users, the network errors only go to the logs
+    let (response_status, error) = match ret {
+        Ok(res) if res.status().is_success() => {
+            return delivery.succeed(res.status().as_u16(), &state.pool).await;
+        }
+        Ok(res) => (
+            Some(res.status().as_u16()),
+            format!("unexpected response status {}", res.status()),
+        ),
+        Err(e) => {
+            warn!(
+                "Webhook delivery {} to {} failed: {}",
+                delivery.id, webhook.url, e
+            );
+            (None, describe_error(&e).to_string())
+        }
+    };
+
+    let status = delivery
+        .fail(
+            response_status,
+            &error,
+            config.max_attempts,
+            backoff,
+            &state.pool,
+        )
+        .await?;
+    if status == DeliveryStatus::Dead {
+        warn!(
+            "Webhook delivery {} to {} is dead after {} attempts: {}",
+            delivery.id, webhook.url, delivery.attempts, error
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        extract::State as AxumState,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+        Router,
+    };
+    use tokio::net::TcpListener;
+
+    use crate::{
+        models::Chat,
+        models::{
+            ChatType, CreateChat, CreateOutgoingWebhook, CreateUser, ListDeliveries, WebhookEvent,
+        },
+        AppConfig, User,
+    };
+
+    use super::*;
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+    async fn receiver(
+        AxumState(received): AxumState<Received>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        received.lock().unwrap().push((headers, body));
+        StatusCode::NO_CONTENT
+    }
+
+    #[tokio::test]
+    async fn deliver_webhooks_should_sign_and_retry() -> anyhow::Result<()> {
+        let mut config = AppConfig::load()?;
+        config.webhooks.max_attempts = 2;
+        config.webhooks.retry_backoff_secs = 0;
+        // the receiver listens on localhost
+        config.outbound.allow_insecure = true;
+        let (_tdb, state) = AppState::try_new_for_test(config).await?;
+
+        let received = Received::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let app = Router::new()
+            .route("/hook", post(receiver))
+            .with_state(received.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let input = CreateUser::new("acme", "Alice", "alice@acme.org", "hunter42");
+        let user = User::create(&input, &state.pool).await?;
+        let input = CreateOutgoingWebhook {
+            url: format!("http://{}/hook", addr),
+            events: vec![WebhookEvent::ChatCreated],
+            chat_id: None,
+        };
+        let (webhook, secret) = OutgoingWebhook::create(
+            &input,
+            &user,
+            &state.config.outbound,
+            &state.ck,
+            &state.pool,
+        )
+        .await?;
+        // the receiver answers 404 for the url of the other webhook
+        let input = CreateOutgoingWebhook {
+            url: format!("http://{}/missing", addr),
+            ..input
+        };
+        let (failing, _) = OutgoingWebhook::create(
+            &input,
+            &user,
+            &state.config.outbound,
+            &state.ck,
+            &state.pool,
+        )
+        .await?;
+
+        let input = CreateChat::new("general", ChatType::PublicChannel, &[]);
+        Chat::create(&input, user.ws_id as _, user.id as _, &state.pool).await?;
+
+        assert_eq!(deliver_webhooks(&state).await?, 2);
+        {
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), 1);
+            let (headers, body) = &received[0];
+            let timestamp: i64 = headers[TIMESTAMP_HEADER].to_str()?.parse()?;
+            let expected = sign_payload(secret.as_bytes(), timestamp, body.as_bytes());
+            assert_eq!(headers[SIGNATURE_HEADER], format!("sha256={}", expected));
+            assert_eq!(headers[EVENT_HEADER], "chat.created");
+        }
+
+        // the failed delivery is retried once, then it's dead
+        assert_eq!(deliver_webhooks(&state).await?, 1);
+        assert_eq!(deliver_webhooks(&state).await?, 0);
+        let input = ListDeliveries::default();
+        let ok = WebhookDelivery::list(webhook.id as _, &input, &state.pool).await?;
+        assert_eq!(ok[0].status, DeliveryStatus::Succeeded);
+        assert_eq!(ok[0].response_status, Some(204));
+        let dead = WebhookDelivery::list(failing.id as _, &input, &state.pool).await?;
+        assert_eq!(dead[0].status, DeliveryStatus::Dead);
+        assert_eq!(dead[0].attempts, 2);
+        assert_eq!(dead[0].response_status, Some(404));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+-- Add migration script here
+
+-- chats belong to a workspace
+ALTER TABLE chats
+  ADD COLUMN ws_id BIGINT REFERENCES workspaces(id);
+
+-- channel metadata for the public channel directory
+ALTER TABLE chats
+  ADD COLUMN topic VARCHAR(256),
+  ADD COLUMN description TEXT,
+  ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE,
+  ADD COLUMN created_by BIGINT REFERENCES users(id);
+
+-- existing chats fall back to the default workspace and superuser
+UPDATE chats SET ws_id = 0 WHERE ws_id IS NULL;
+UPDATE chats SET created_by = 0 WHERE created_by IS NULL;
+
+ALTER TABLE chats
+  ALTER COLUMN ws_id SET NOT NULL,
+  ALTER COLUMN created_by SET NOT NULL;
+
+-- create index for chats.ws_id and type, used by the channel directory
+CREATE INDEX IF NOT EXISTS chats_ws_id_type_idx ON chats(ws_id, type);
@@ -0,0 +1,63 @@
+-- Add migration script here
+
+-- users mentioned in a message
+ALTER TABLE messages
+  ADD COLUMN mentions BIGINT[] NOT NULL DEFAULT '{}';
+
+-- create notify level: all messages, mentions only, or nothing
+CREATE TYPE notify_level AS ENUM ('all', 'mentions', 'none');
+
+-- per chat notification preferences of a user
+CREATE TABLE IF NOT EXISTS chat_notify_settings (
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    chat_id BIGINT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    level notify_level NOT NULL DEFAULT 'all',
+    muted_until TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, chat_id)
+);
+
+-- per user settings, dnd_start and dnd_end are local times in the given timezone
+CREATE TABLE IF NOT EXISTS user_settings (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id),
+    dnd_enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    dnd_start TIME NOT NULL DEFAULT '22:00',
+    dnd_end TIME NOT NULL DEFAULT '08:00',
+    timezone VARCHAR(64) NOT NULL DEFAULT 'UTC',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- notify notify_server when a chat is created, updated or deleted
+CREATE OR REPLACE FUNCTION chat_updated()
+  RETURNS TRIGGER
+  AS $$
+BEGIN
+  PERFORM pg_notify('chat_updated', json_build_object('op', TG_OP, 'old', OLD, 'new', NEW)::text);
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER chat_updated_trigger
+  AFTER INSERT OR UPDATE OR DELETE ON chats
+  FOR EACH ROW
+  EXECUTE FUNCTION chat_updated();
+
+-- notify notify_server when a message is created, together with the chat members
+CREATE OR REPLACE FUNCTION message_created()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  USERS bigint[];
+BEGIN
+  SELECT members INTO USERS FROM chats WHERE id = NEW.chat_id;
+  PERFORM pg_notify('chat_message_created', json_build_object('message', NEW, 'members', USERS)::text);
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER message_created_trigger
+  AFTER INSERT ON messages
+  FOR EACH ROW
+  EXECUTE FUNCTION message_created();
@@ -0,0 +1,22 @@
+-- Add migration script here
+
+-- the last message a user has read in a chat
+CREATE TABLE IF NOT EXISTS chat_reads (
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    chat_id BIGINT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    last_read_id BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, chat_id)
+);
+
+-- email digest preferences, digest_delay is in minutes, NULL uses the server default
+ALTER TABLE user_settings
+  ADD COLUMN digest_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+  ADD COLUMN digest_delay INT;
+
+-- the last message included in an email digest for a user
+CREATE TABLE IF NOT EXISTS email_digests (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id),
+    last_message_id BIGINT NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,22 @@
+-- Add migration script here
+
+-- users created before email verification existed are trusted
+ALTER TABLE users
+  ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE users SET email_verified = TRUE;
+
+-- create user token kind: verify email, reset password
+CREATE TYPE user_token_kind AS ENUM ('verify_email', 'reset_password');
+
+-- single-use tokens sent to the user by email, only the sha256 hash is stored
+CREATE TABLE IF NOT EXISTS user_tokens (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    kind user_token_kind NOT NULL,
+    token_hash CHAR(64) NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS user_tokens_user_id_idx ON user_tokens(user_id, kind);
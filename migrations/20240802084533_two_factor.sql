@@ -0,0 +1,25 @@
+-- Add migration script here
+
+-- workspace owners can require 2fa for all members
+ALTER TABLE workspaces
+  ADD COLUMN require_2fa BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- totp secret of a user, encrypted with the server key. it is only enabled after the
+-- user proved the authenticator works, last_used_step prevents replaying a code
+CREATE TABLE IF NOT EXISTS user_totp (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id),
+    secret BYTEA NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    last_used_step BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- one-time recovery codes, only the sha256 hash is stored
+CREATE TABLE IF NOT EXISTS user_recovery_codes (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    code_hash CHAR(64) NOT NULL,
+    used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS user_recovery_codes_user_id_idx ON user_recovery_codes(user_id);
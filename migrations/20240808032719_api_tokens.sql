@@ -0,0 +1,27 @@
+-- Add migration script here
+
+-- what an api token is allowed to do
+CREATE TYPE api_scope AS ENUM (
+  'user:read',
+  'user:write',
+  'chat:read',
+  'chat:write',
+  'message:read',
+  'message:write'
+);
+
+-- long-lived tokens for scripts and integrations, only the sha256 hash is stored.
+-- the prefix is the start of the token, to tell tokens apart when listing them
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(64) NOT NULL,
+    prefix VARCHAR(16) NOT NULL,
+    token_hash CHAR(64) NOT NULL UNIQUE,
+    scopes api_scope[] NOT NULL,
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS api_tokens_user_id_idx ON api_tokens(user_id);
@@ -0,0 +1,25 @@
+-- Add migration script here
+
+-- create user kind: human, bot. bots can't sign in, they post on behalf of integrations
+CREATE TYPE user_kind AS ENUM ('human', 'bot');
+
+ALTER TABLE users
+  ADD COLUMN kind user_kind NOT NULL DEFAULT 'human';
+
+-- integrations may post under another name than their bot user
+ALTER TABLE messages
+  ADD COLUMN sender_name VARCHAR(64);
+
+-- each incoming webhook posts into one chat as its own bot user.
+-- only the sha256 hash of the secret token in the webhook url is stored
+CREATE TABLE IF NOT EXISTS incoming_webhooks (
+    id BIGSERIAL PRIMARY KEY,
+    chat_id BIGINT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    bot_id BIGINT NOT NULL REFERENCES users(id),
+    name VARCHAR(64) NOT NULL,
+    token_hash CHAR(64) NOT NULL UNIQUE,
+    created_by BIGINT NOT NULL REFERENCES users(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS incoming_webhooks_chat_id_idx ON incoming_webhooks(chat_id);
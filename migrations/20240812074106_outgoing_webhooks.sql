@@ -0,0 +1,105 @@
+-- Add migration script here
+
+-- create webhook event: message.created, chat.created, member.joined
+CREATE TYPE webhook_event AS ENUM ('message.created', 'chat.created', 'member.joined');
+
+-- create webhook delivery status: pending (also while retrying), succeeded, dead
+CREATE TYPE webhook_delivery_status AS ENUM ('pending', 'succeeded', 'dead');
+
+-- webhooks of the whole workspace have no chat. the secret signing the payloads
+-- is encrypted with the server key
+CREATE TABLE IF NOT EXISTS outgoing_webhooks (
+    id BIGSERIAL PRIMARY KEY,
+    ws_id BIGINT NOT NULL REFERENCES workspaces(id),
+    chat_id BIGINT REFERENCES chats(id) ON DELETE CASCADE,
+    url VARCHAR(2048) NOT NULL,
+    secret BYTEA NOT NULL,
+    events webhook_event[] NOT NULL,
+    created_by BIGINT NOT NULL REFERENCES users(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS outgoing_webhooks_ws_id_idx ON outgoing_webhooks(ws_id);
+
+-- the job queue of the deliveries, which is also their log
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id BIGSERIAL PRIMARY KEY,
+    webhook_id BIGINT NOT NULL REFERENCES outgoing_webhooks(id) ON DELETE CASCADE,
+    event webhook_event NOT NULL,
+    payload JSONB NOT NULL,
+    status webhook_delivery_status NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    response_status INT,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_pending_idx ON webhook_deliveries(next_attempt_at)
+  WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS webhook_deliveries_webhook_id_idx ON webhook_deliveries(webhook_id, id DESC);
+
+-- queue a delivery for every webhook of the workspace or chat subscribed to the event.
+-- deliveries are queued in the transaction of the change, so none is lost
+CREATE OR REPLACE FUNCTION enqueue_webhook_deliveries(_ws_id BIGINT, _chat_id BIGINT, _event webhook_event, _data JSONB)
+  RETURNS VOID
+  AS $$
+BEGIN
+  INSERT INTO webhook_deliveries (webhook_id, event, payload)
+  SELECT w.id, _event, jsonb_build_object(
+      'event', _event,
+      'workspace_id', _ws_id,
+      'chat_id', _chat_id,
+      'data', _data,
+      'created_at', CURRENT_TIMESTAMP)
+  FROM outgoing_webhooks w
+  WHERE w.ws_id = _ws_id
+    AND (w.chat_id IS NULL OR w.chat_id = _chat_id)
+    AND _event = ANY(w.events);
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION message_created_webhooks()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  CHAT_WS_ID bigint;
+BEGIN
+  SELECT ws_id INTO CHAT_WS_ID FROM chats WHERE id = NEW.chat_id;
+  PERFORM enqueue_webhook_deliveries(CHAT_WS_ID, NEW.chat_id, 'message.created', to_jsonb(NEW));
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER message_created_webhooks_trigger
+  AFTER INSERT ON messages
+  FOR EACH ROW
+  EXECUTE FUNCTION message_created_webhooks();
+
+-- a chat is created, or members join one
+CREATE OR REPLACE FUNCTION chat_webhooks()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  JOINED_ID bigint;
+BEGIN
+  IF TG_OP = 'INSERT' THEN
+    PERFORM enqueue_webhook_deliveries(NEW.ws_id, NEW.id, 'chat.created', to_jsonb(NEW));
+  ELSE
+    FOR JOINED_ID IN SELECT unnest(NEW.members) EXCEPT SELECT unnest(OLD.members) LOOP
+      PERFORM enqueue_webhook_deliveries(NEW.ws_id, NEW.id, 'member.joined',
+        jsonb_build_object('chat_id', NEW.id, 'user_id', JOINED_ID));
+    END LOOP;
+  END IF;
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER chat_webhooks_trigger
+  AFTER INSERT OR UPDATE OF members ON chats
+  FOR EACH ROW
+  EXECUTE FUNCTION chat_webhooks();
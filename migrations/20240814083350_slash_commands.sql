@@ -0,0 +1,28 @@
+-- Add migration script here
+
+-- custom slash commands of a workspace, answered by an http callback. the secret
+-- signing the callbacks is encrypted with the server key
+CREATE TABLE IF NOT EXISTS slash_commands (
+    id BIGSERIAL PRIMARY KEY,
+    ws_id BIGINT NOT NULL REFERENCES workspaces(id),
+    name VARCHAR(32) NOT NULL,
+    url VARCHAR(2048) NOT NULL,
+    description VARCHAR(256) NOT NULL DEFAULT '',
+    secret BYTEA NOT NULL,
+    created_by BIGINT NOT NULL REFERENCES users(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (ws_id, name)
+);
+
+-- reminders set with /remind
+CREATE TABLE IF NOT EXISTS reminders (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    text TEXT NOT NULL,
+    remind_at TIMESTAMPTZ NOT NULL,
+    sent_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS reminders_user_id_idx ON reminders(user_id);
@@ -0,0 +1,43 @@
+-- Add migration script here
+
+-- messages posted by the scheduler at send_at. sent_at is set when the scheduler
+-- claims the message, then it records the posted message or why it couldn't be posted
+CREATE TABLE IF NOT EXISTS scheduled_messages (
+    id BIGSERIAL PRIMARY KEY,
+    chat_id BIGINT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    sender_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    images TEXT[] NOT NULL DEFAULT '{}',
+    mentions BIGINT[] NOT NULL DEFAULT '{}',
+    send_at TIMESTAMPTZ NOT NULL,
+    sent_at TIMESTAMPTZ,
+    message_id BIGINT REFERENCES messages(id) ON DELETE SET NULL,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS scheduled_messages_sender_id_idx ON scheduled_messages(sender_id);
+CREATE INDEX IF NOT EXISTS scheduled_messages_due_idx ON scheduled_messages(send_at)
+  WHERE sent_at IS NULL;
+
+-- "remind me about this message"
+ALTER TABLE reminders ADD COLUMN message_id BIGINT REFERENCES messages(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS reminders_due_idx ON reminders(remind_at) WHERE sent_at IS NULL;
+
+-- notify notify_server when the scheduler sends a reminder
+CREATE OR REPLACE FUNCTION reminder_sent()
+  RETURNS TRIGGER
+  AS $$
+BEGIN
+  PERFORM pg_notify('reminder_sent', row_to_json(NEW)::text);
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER reminder_sent_trigger
+  AFTER UPDATE OF sent_at ON reminders
+  FOR EACH ROW
+  WHEN (OLD.sent_at IS NULL AND NEW.sent_at IS NOT NULL)
+  EXECUTE FUNCTION reminder_sent();
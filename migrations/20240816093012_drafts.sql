@@ -0,0 +1,27 @@
+-- Add migration script here
+
+-- the draft of a user in a chat, shared by the devices of the user. the latest
+-- change wins, an empty content is a cleared draft
+CREATE TABLE IF NOT EXISTS drafts (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, chat_id)
+);
+
+-- notify notify_server when a draft changes, so the other devices of the user get it
+CREATE OR REPLACE FUNCTION draft_updated()
+  RETURNS TRIGGER
+  AS $$
+BEGIN
+  PERFORM pg_notify('draft_updated', row_to_json(NEW)::text);
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER draft_updated_trigger
+  AFTER INSERT OR UPDATE ON drafts
+  FOR EACH ROW
+  EXECUTE FUNCTION draft_updated();
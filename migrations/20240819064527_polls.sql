@@ -0,0 +1,17 @@
+-- Add migration script here
+
+-- the payload of a message depends on its kind, e.g. the options of a poll
+CREATE TYPE message_kind AS ENUM ('text', 'system', 'poll', 'file');
+
+ALTER TABLE messages
+  ADD COLUMN kind message_kind NOT NULL DEFAULT 'text',
+  ADD COLUMN payload JSONB;
+
+-- a user has one row per option they voted for
+CREATE TABLE IF NOT EXISTS poll_votes (
+    message_id BIGINT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    option INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (message_id, user_id, option)
+);
@@ -0,0 +1,20 @@
+-- Add migration script here
+
+-- the trace context of the transaction which created the message, set with
+-- set_config('chat.traceparent', ..., true), so notify_server can continue the trace
+CREATE OR REPLACE FUNCTION message_created()
+  RETURNS TRIGGER
+  AS $$
+DECLARE
+  USERS bigint[];
+BEGIN
+  SELECT members INTO USERS FROM chats WHERE id = NEW.chat_id;
+  PERFORM pg_notify('chat_message_created', json_build_object(
+    'message', NEW,
+    'members', USERS,
+    'traceparent', NULLIF(current_setting('chat.traceparent', true), '')
+  )::text);
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
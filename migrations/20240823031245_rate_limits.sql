@@ -0,0 +1,11 @@
+-- Add migration script here
+
+-- token buckets of the postgres rate limit backend, keyed by route group and
+-- client, e.g. "public:10.0.0.1" or "api:42"
+CREATE TABLE IF NOT EXISTS rate_limits (
+    key VARCHAR(128) PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS rate_limits_updated_at_idx ON rate_limits(updated_at);
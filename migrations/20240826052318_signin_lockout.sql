@@ -0,0 +1,24 @@
+-- Add migration script here
+
+-- failed password sign-ins in a row, the account is locked once they reach the
+-- limit and unlocked by a successful sign-in after the lock or by an admin
+ALTER TABLE users
+  ADD COLUMN failed_signins INT NOT NULL DEFAULT 0,
+  ADD COLUMN locked_until TIMESTAMPTZ;
+
+-- create signin result: success, bad_password, unknown_user, locked, ip_blocked
+CREATE TYPE signin_result AS ENUM ('success', 'bad_password', 'unknown_user', 'locked', 'ip_blocked');
+
+-- every password sign-in, user_id is null when the email is unknown
+CREATE TABLE IF NOT EXISTS signin_events (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT REFERENCES users(id),
+    email VARCHAR(64) NOT NULL,
+    ip VARCHAR(64),
+    user_agent VARCHAR(256),
+    result signin_result NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS signin_events_user_id_idx ON signin_events(user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS signin_events_ip_idx ON signin_events(ip, created_at);
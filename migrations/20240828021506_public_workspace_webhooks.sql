@@ -0,0 +1,24 @@
+-- Add migration script here
+
+-- webhooks of the whole workspace only get the events of public channels, the
+-- ones of direct messages, groups and private channels need a webhook of the chat
+CREATE OR REPLACE FUNCTION enqueue_webhook_deliveries(_ws_id BIGINT, _chat_id BIGINT, _event webhook_event, _data JSONB)
+  RETURNS VOID
+  AS $$
+BEGIN
+  INSERT INTO webhook_deliveries (webhook_id, event, payload)
+  SELECT w.id, _event, jsonb_build_object(
+      'event', _event,
+      'workspace_id', _ws_id,
+      'chat_id', _chat_id,
+      'data', _data,
+      'created_at', CURRENT_TIMESTAMP)
+  FROM outgoing_webhooks w
+  WHERE w.ws_id = _ws_id
+    AND (w.chat_id = _chat_id
+      OR (w.chat_id IS NULL
+        AND EXISTS (SELECT 1 FROM chats WHERE id = _chat_id AND type = 'public_channel')))
+    AND _event = ANY(w.events);
+END;
+$$
+LANGUAGE plpgsql;
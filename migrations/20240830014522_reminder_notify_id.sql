@@ -0,0 +1,13 @@
+-- Add migration script here
+
+-- pg_notify payloads are limited to 8000 bytes, a long reminder made the update
+-- of the whole batch fail. notify_server loads the reminder by its id instead
+CREATE OR REPLACE FUNCTION reminder_sent()
+  RETURNS TRIGGER
+  AS $$
+BEGIN
+  PERFORM pg_notify('reminder_sent', json_build_object('id', NEW.id)::text);
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
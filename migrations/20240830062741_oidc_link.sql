@@ -0,0 +1,6 @@
+-- Add migration script here
+
+-- a login started by a signed-in user links the identity to that user. other
+-- logins never link to an existing account, even with the same email
+ALTER TABLE oidc_logins
+  ADD COLUMN user_id BIGINT REFERENCES users(id) ON DELETE CASCADE;
@@ -0,0 +1,12 @@
+-- Add migration script here
+
+-- wrong codes entered for a sign-in challenge. a challenge is refused after a few
+-- of them, and a user after too many over all of their recent challenges
+CREATE TABLE IF NOT EXISTS totp_failures (
+    challenge_id VARCHAR(64) PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    failures INT NOT NULL DEFAULT 1,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS totp_failures_user_id_idx ON totp_failures(user_id, created_at);
@@ -0,0 +1,18 @@
+-- Add migration script here
+
+-- a user has a single vote on a single choice poll, so concurrent votes can't
+-- leave them with several. keep the latest vote of the ones there are already
+ALTER TABLE poll_votes ADD COLUMN single BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE poll_votes v
+SET single = TRUE
+FROM messages m
+WHERE m.id = v.message_id AND m.payload->>'multiple' = 'false';
+
+DELETE FROM poll_votes v
+USING poll_votes newer
+WHERE v.single AND newer.single
+  AND v.message_id = newer.message_id AND v.user_id = newer.user_id
+  AND (newer.created_at, newer.option) > (v.created_at, v.option);
+
+CREATE UNIQUE INDEX IF NOT EXISTS poll_votes_single_idx ON poll_votes(message_id, user_id) WHERE single;
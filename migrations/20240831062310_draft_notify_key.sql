@@ -0,0 +1,14 @@
+-- Add migration script here
+
+-- a long draft made the upsert fail on the 8000 bytes limit of pg_notify payloads.
+-- notify_server loads the draft by its key instead
+CREATE OR REPLACE FUNCTION draft_updated()
+  RETURNS TRIGGER
+  AS $$
+BEGIN
+  PERFORM pg_notify('draft_updated',
+    json_build_object('user_id', NEW.user_id, 'chat_id', NEW.chat_id)::text);
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
@@ -0,0 +1,16 @@
+-- Add migration script here
+
+-- a right password of a user with 2fa, and a wrong code at the second step
+ALTER TYPE signin_result ADD VALUE IF NOT EXISTS 'two_factor_pending';
+ALTER TYPE signin_result ADD VALUE IF NOT EXISTS 'bad_two_factor';
+
+-- failed sign-ins of emails without an account. they are locked like accounts,
+-- so the answers don't tell which emails have one
+CREATE TABLE IF NOT EXISTS unknown_signins (
+    email VARCHAR(64) PRIMARY KEY,
+    failures INT NOT NULL DEFAULT 0,
+    locked_until TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS unknown_signins_updated_at_idx ON unknown_signins(updated_at);
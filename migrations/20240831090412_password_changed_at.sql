@@ -0,0 +1,5 @@
+-- Add migration script here
+
+-- access tokens issued before the password was last changed are revoked
+ALTER TABLE users
+  ADD COLUMN password_changed_at TIMESTAMPTZ;
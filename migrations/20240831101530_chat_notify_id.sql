@@ -0,0 +1,30 @@
+-- Add migration script here
+
+-- a whole chat row could be too long for a pg_notify payload, e.g. with a long
+-- description, so notify_server loads the chat. members an update removed aren't
+-- in the chat any more and are sent along. a deleted chat can't be loaded, it's
+-- sent without its free text
+CREATE OR REPLACE FUNCTION chat_updated()
+  RETURNS TRIGGER
+  AS $$
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    PERFORM pg_notify('chat_updated', json_build_object(
+      'op', TG_OP,
+      'id', OLD.id,
+      'old', to_jsonb(OLD) - 'topic' - 'description'
+    )::text);
+    RETURN OLD;
+  ELSIF TG_OP = 'UPDATE' THEN
+    PERFORM pg_notify('chat_updated', json_build_object(
+      'op', TG_OP,
+      'id', NEW.id,
+      'removed', ARRAY(SELECT unnest(OLD.members) EXCEPT SELECT unnest(NEW.members))
+    )::text);
+  ELSE
+    PERFORM pg_notify('chat_updated', json_build_object('op', TG_OP, 'id', NEW.id)::text);
+  END IF;
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
@@ -0,0 +1,16 @@
+-- Add migration script here
+
+-- a whole message row could be too long for a pg_notify payload, so only its id
+-- and the trace context are sent, notify_server loads the message and the members
+CREATE OR REPLACE FUNCTION message_created()
+  RETURNS TRIGGER
+  AS $$
+BEGIN
+  PERFORM pg_notify('chat_message_created', json_build_object(
+    'id', NEW.id,
+    'traceparent', NULLIF(current_setting('chat.traceparent', true), '')
+  )::text);
+  RETURN NEW;
+END;
+$$
+LANGUAGE plpgsql;
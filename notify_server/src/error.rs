@@ -0,0 +1,58 @@
+use axum::{
+    http::{Response, StatusCode},
+    response::IntoResponse,
+};
+use chat_core::Problem;
+use thiserror::Error;
+use tracing::error;
+
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error("push is not enabled")]
+    PushDisabled,
+
+    #[error("invalid push subscription: {0}")]
+    InvalidSubscription(String),
+
+    #[error("not found: {0}")]
+    NotFound(String),
+
+    #[error("sql error: {0}")]
+    SqlxError(#[from] sqlx::Error),
+}
+
+impl AppError {
+    // stable, for clients to switch on
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::PushDisabled => "push_disabled",
+            AppError::InvalidSubscription(_) => "invalid_subscription",
+            AppError::NotFound(_) => "not_found",
+            AppError::SqlxError(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::PushDisabled => StatusCode::NOT_FOUND,
+            AppError::InvalidSubscription(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::SqlxError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response<axum::body::Body> {
+        let status = self.status();
+        // server errors may contain sql, they are only logged
+        let detail = if status.is_server_error() {
+            error!("Request failed with {}: {:?}", self.code(), self);
+            "the server failed to handle the request".to_string()
+        } else {
+            self.to_string()
+        };
+
+        Problem::new(status, self.code(), detail).into_response()
+    }
+}
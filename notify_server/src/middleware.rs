@@ -0,0 +1,65 @@
+use axum::{
+    extract::{FromRequestParts, Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::{
+    headers::{authorization::Bearer, Authorization},
+    TypedHeader,
+};
+use chat_core::Problem;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{AppError, AppState, User};
+
+#[derive(Debug, Deserialize)]
+struct AccessToken {
+    access_token: String,
+}
+
+// EventSource can't set headers, so the token may also come from `?access_token=`
+pub async fn verify_token(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let (mut parts, body) = req.into_parts();
+
+    let token = match TypedHeader::<Authorization<Bearer>>::from_request_parts(&mut parts, &state)
+        .await
+    {
+        Ok(TypedHeader(Authorization(bearer))) => bearer.token().to_string(),
+        Err(e) => match Query::<AccessToken>::from_request_parts(&mut parts, &state).await {
+            Ok(Query(query)) => query.access_token,
+            Err(_) => {
+                let msg = format!("Error parsing Authorization header: {}", e);
+                warn!(msg);
+                return Problem::new(StatusCode::UNAUTHORIZED, "unauthorized", msg).into_response();
+            }
+        },
+    };
+
+    let req = match state.dk.verify_issued::<User>(&token) {
+        Ok((user, issued_at)) => {
+            match chat_core::token_revoked(user.id, issued_at, &state.pool).await {
+                Ok(false) => {}
+                Ok(true) => {
+                    let msg = "Error verifying token: revoked by a password change";
+                    warn!(msg);
+                    return Problem::new(StatusCode::FORBIDDEN, "invalid_token", msg)
+                        .into_response();
+                }
+                Err(e) => return AppError::from(e).into_response(),
+            }
+            chat_core::record_user(user.id, user.ws_id);
+            let mut req = Request::from_parts(parts, body);
+            req.extensions_mut().insert(user);
+            req
+        }
+        Err(e) => {
+            let msg = format!("Error verifying token: {}", e);
+            warn!(msg);
+            return Problem::new(StatusCode::FORBIDDEN, "invalid_token", msg).into_response();
+        }
+    };
+
+    next.run(req).await
+}
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "notify_level", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyLevel {
+    #[default]
+    All,
+    Mentions,
+    None,
+}
+
+// do-not-disturb window in the user's local time, may wrap over midnight
+#[derive(Debug, Clone, PartialEq)]
+pub struct DndSchedule {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub tz: Tz,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NotifyPref {
+    pub level: NotifyLevel,
+    pub muted_until: Option<DateTime<Utc>>,
+    pub dnd: Option<DndSchedule>,
+}
+
+#[derive(Debug, FromRow)]
+struct NotifyPrefRow {
+    user_id: i64,
+    level: Option<NotifyLevel>,
+    muted_until: Option<DateTime<Utc>>,
+    dnd_enabled: Option<bool>,
+    dnd_start: Option<NaiveTime>,
+    dnd_end: Option<NaiveTime>,
+    timezone: Option<String>,
+}
+
+impl DndSchedule {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.tz).time();
+        if self.start <= self.end {
+            local >= self.start && local < self.end
+        } else {
+            local >= self.start || local < self.end
+        }
+    }
+}
+
+impl NotifyPref {
+    // whether a new message should notify the user, or only be synced silently
+    pub fn should_notify(&self, mentioned: bool, now: DateTime<Utc>) -> bool {
+        if self.muted_until.is_some_and(|until| until > now) {
+            return false;
+        }
+
+        if self.dnd.as_ref().is_some_and(|dnd| dnd.is_active(now)) {
+            return false;
+        }
+
+        match self.level {
+            NotifyLevel::All => true,
+            NotifyLevel::Mentions => mentioned,
+            NotifyLevel::None => false,
+        }
+    }
+
+    pub async fn load_all(
+        chat_id: u64,
+        user_ids: &[u64],
+        pool: &PgPool,
+    ) -> Result<HashMap<u64, Self>, sqlx::Error> {
+        let user_ids: Vec<i64> = user_ids.iter().map(|id| *id as i64).collect();
+        let rows: Vec<NotifyPrefRow> = sqlx::query_as(
+            r#"
+            SELECT u.id AS user_id, c.level, c.muted_until,
+                s.dnd_enabled, s.dnd_start, s.dnd_end, s.timezone
+            FROM unnest($2::BIGINT[]) AS u(id)
+            LEFT JOIN chat_notify_settings c ON c.user_id = u.id AND c.chat_id = $1
+            LEFT JOIN user_settings s ON s.user_id = u.id
+            "#,
+        )
+        .bind(chat_id as i64)
+        .bind(&user_ids)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.user_id as u64, row.into()))
+            .collect())
+    }
+}
+
+impl From<NotifyPrefRow> for NotifyPref {
+    fn from(row: NotifyPrefRow) -> Self {
+        let dnd = match (row.dnd_enabled, row.dnd_start, row.dnd_end, row.timezone) {
+            (Some(true), Some(start), Some(end), Some(tz)) => match tz.parse() {
+                Ok(tz) => Some(DndSchedule { start, end, tz }),
+                Err(e) => {
+                    warn!("Invalid timezone {} for user {}: {}", tz, row.user_id, e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        Self {
+            level: row.level.unwrap_or_default(),
+            muted_until: row.muted_until,
+            dnd,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn dnd_schedule_should_work() {
+        let dnd = DndSchedule {
+            start: time(22, 0),
+            end: time(8, 0),
+            tz: chrono_tz::Asia::Shanghai,
+        };
+
+        // 15:00 UTC is 23:00 in Shanghai
+        let now = Utc.with_ymd_and_hms(2024, 7, 1, 15, 0, 0).unwrap();
+        assert!(dnd.is_active(now));
+        // 04:00 UTC is 12:00 in Shanghai
+        let now = Utc.with_ymd_and_hms(2024, 7, 1, 4, 0, 0).unwrap();
+        assert!(!dnd.is_active(now));
+
+        let dnd = DndSchedule {
+            start: time(9, 0),
+            end: time(17, 0),
+            tz: chrono_tz::UTC,
+        };
+        let now = Utc.with_ymd_and_hms(2024, 7, 1, 12, 0, 0).unwrap();
+        assert!(dnd.is_active(now));
+        let now = Utc.with_ymd_and_hms(2024, 7, 1, 17, 0, 0).unwrap();
+        assert!(!dnd.is_active(now));
+    }
+
+    #[test]
+    fn should_notify_should_respect_prefs() {
+        let now = Utc::now();
+
+        let pref = NotifyPref::default();
+        assert!(pref.should_notify(false, now));
+
+        let pref = NotifyPref {
+            level: NotifyLevel::Mentions,
+            ..Default::default()
+        };
+        assert!(!pref.should_notify(false, now));
+        assert!(pref.should_notify(true, now));
+
+        let pref = NotifyPref {
+            level: NotifyLevel::None,
+            ..Default::default()
+        };
+        assert!(!pref.should_notify(true, now));
+
+        let pref = NotifyPref {
+            muted_until: Some(now + Duration::minutes(10)),
+            ..Default::default()
+        };
+        assert!(!pref.should_notify(true, now));
+
+        // mute has expired
+        let pref = NotifyPref {
+            muted_until: Some(now - Duration::minutes(10)),
+            ..Default::default()
+        };
+        assert!(pref.should_notify(false, now));
+
+        let pref = NotifyPref {
+            dnd: Some(DndSchedule {
+                start: time(0, 0),
+                end: time(23, 59),
+                tz: chrono_tz::UTC,
+            }),
+            ..Default::default()
+        };
+        let now = Utc.with_ymd_and_hms(2024, 7, 1, 12, 0, 0).unwrap();
+        assert!(!pref.should_notify(true, now));
+    }
+}
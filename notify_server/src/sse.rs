@@ -1,22 +1,71 @@
 use std::{convert::Infallible, time::Duration};
 
-use axum::response::sse::{Event, Sse};
-use axum_extra::{headers, TypedHeader};
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
 use futures::{stream, Stream};
-use tokio_stream::StreamExt;
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tracing::{info, warn};
+
+use crate::{AppState, User};
 
 pub(crate) async fn sse_handler(
-    TypedHeader(user_agent): TypedHeader<headers::UserAgent>,
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    println!("User-Agent: {}", user_agent);
+    let user_id = user.id as u64;
+    let rx = state
+        .users
+        .entry(user_id)
+        .or_insert_with(|| broadcast::channel(state.config.sse.channel_capacity).0)
+        .subscribe();
+    info!("User {} subscribed", user_id);
+
+    let stream = BroadcastStream::new(rx).filter_map(|v| v.ok()).map(|v| {
+        let name = v.event.name();
+        let data = serde_json::to_string(&v).unwrap_or_else(|e| {
+            warn!("Failed to serialize event {}: {}", name, e);
+            "{}".to_string()
+        });
+        Ok(Event::default().data(data).event(name))
+    });
 
-    let stream = stream::repeat_with(|| Event::default().data("hello"))
-        .map(Ok)
-        .throttle(Duration::from_secs(1));
+    // counts the connection until the stream is dropped
+    let guard = SseConnection::new();
+    let stream = stream.map(move |event| {
+        let _ = &guard;
+        event
+    });
+
+    // on shutdown, tell the client to reconnect to another instance and end the
+    // stream so the connection can be drained
+    let readiness = state.readiness.clone();
+    let stream = futures::StreamExt::take_until(stream, async move { readiness.draining().await })
+        .chain(stream::once(async {
+            Ok(Event::default().event("reconnect").data("{}"))
+        }));
 
     Sse::new(stream).keep_alive(
-        axum::response::sse::KeepAlive::new()
-            .interval(Duration::from_secs(1))
-            .text("ping"),
+        KeepAlive::new()
+            .interval(Duration::from_secs(state.config.sse.keep_alive_secs))
+            .text("keep-alive-text"),
     )
 }
+
+struct SseConnection;
+
+impl SseConnection {
+    fn new() -> Self {
+        metrics::gauge!("sse_connections").increment(1.0);
+        Self
+    }
+}
+
+impl Drop for SseConnection {
+    fn drop(&mut self) {
+        metrics::gauge!("sse_connections").decrement(1.0);
+    }
+}